@@ -0,0 +1,35 @@
+//! Tokenizes the corpus repeated to growing sizes. With a linear tokenizer the
+//! throughput (bytes/s) stays flat from the smallest to the largest input.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use htmlfmt::tokenize::tokenizer::Tokenizer;
+use std::hint::black_box;
+
+const PAGE: &str = include_str!("corpus/page.html");
+
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    group.sample_size(10);
+
+    // 約 1KB から約 3MB まで。2MB を超える生成ページも線形で読めることを見る
+    for repeat in [1, 16, 256, 2048, 4096] {
+        let input = PAGE.repeat(repeat);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(input.len()),
+            &input,
+            |b, input| {
+                // drop は計測から外す
+                b.iter_with_large_drop(|| {
+                    let mut tokenizer = Tokenizer::new(black_box(input));
+                    tokenizer.tokenize()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, tokenize);
+criterion_main!(benches);
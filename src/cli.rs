@@ -0,0 +1,11 @@
+pub mod args;
+pub mod diff;
+pub mod err;
+pub mod input;
+pub mod lsp;
+pub mod pool;
+pub mod resolve;
+pub mod runner;
+pub mod status;
+pub mod walk;
+pub mod write;
@@ -0,0 +1,135 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use htmlfmt::format::config::Config;
+use std::io::IsTerminal;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+fn parse_range(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = match s.split_once(':') {
+        Some(pair) => pair,
+        None => return Err("expected START:END".to_string()),
+    };
+    let start: usize = start.parse().map_err(|e| format!("invalid START: {}", e))?;
+    let end: usize = end.parse().map_err(|e| format!("invalid END: {}", e))?;
+    if end < start {
+        return Err("END must not be before START".to_string());
+    }
+    return Ok((start, end));
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a Language Server Protocol server over stdio, for editors'
+    /// format-on-save.
+    Lsp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Color {
+    /// Colour when stdout is a terminal and `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+/// Format HTML files.
+#[derive(Debug, Parser)]
+#[command(
+    name = "htmlfmt",
+    version,
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit status: 0 if everything is formatted, 1 if --check found files that \
+                  need formatting, 2 if a file could not be read or parsed."
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Files or directories to format. Directories are walked recursively.
+    /// `-` (or no file at all) reads from stdin.
+    #[arg(value_name = "PATH")]
+    pub files: Vec<PathBuf>,
+
+    /// Glob of files to pick when walking directories (repeatable).
+    /// Defaults to `*.html` and `*.htm`.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Glob of files or directories to skip when walking directories
+    /// (repeatable). Paths listed in `.htmlfmtignore` are skipped as well.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Don't print the formatted output; list the files whose formatting
+    /// differs and exit with 1 if there are any.
+    #[arg(long)]
+    pub check: bool,
+
+    /// Print a unified diff of the changes instead of the formatted output.
+    /// With --check, the diff is printed in place of the file list.
+    #[arg(long)]
+    pub diff: bool,
+
+    /// When to colour the --diff output.
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = Color::Auto)]
+    pub color: Color,
+
+    /// Overwrite the files in place instead of printing them. Files that are
+    /// already formatted are left untouched.
+    #[arg(long, conflicts_with_all = ["check", "diff"])]
+    pub write: bool,
+
+    /// Format around parse errors instead of failing: the parts that don't
+    /// parse are kept as written and the errors are printed as warnings.
+    /// Without --range, such files no longer exit with 2.
+    #[arg(long)]
+    pub recover: bool,
+
+    /// Maximum number of files formatted at once. Defaults to the number of
+    /// CPU cores.
+    #[arg(long, short = 'j', value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Only reformat the elements overlapping lines START to END (1-based,
+    /// inclusive). Everything else is left byte-for-byte as it is.
+    #[arg(long, value_name = "START:END", value_parser = parse_range)]
+    pub range: Option<(usize, usize)>,
+
+    /// Like --range, but with byte offsets (END exclusive).
+    #[arg(
+        long,
+        value_name = "START:END",
+        value_parser = parse_range,
+        conflicts_with = "range"
+    )]
+    pub byte_range: Option<(usize, usize)>,
+
+    /// Use this config file for every input instead of looking up the
+    /// nearest `htmlfmt.toml`.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Number of spaces per indentation level.
+    #[arg(long, value_name = "N")]
+    pub indent: Option<usize>,
+}
+
+impl Args {
+    /// Applies the formatting options given on the command line, which take
+    /// precedence over any config file.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(indent) = self.indent {
+            config.ident = indent;
+        }
+    }
+
+    pub fn color_enabled(&self) -> bool {
+        return match self.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => {
+                std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+            }
+        };
+    }
+}
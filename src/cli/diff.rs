@@ -0,0 +1,84 @@
+use similar::TextDiff;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+
+/// Unified diff between `source` and its formatted version, with 3 lines of
+/// context. Returns an empty string when both are identical.
+pub fn unified_diff(name: &str, source: &str, formatted: &str, color: bool) -> String {
+    if source == formatted {
+        return "".to_string();
+    }
+
+    let diff = TextDiff::from_lines(source, formatted)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string();
+
+    if !color {
+        return diff;
+    }
+    return colorize(&diff);
+}
+
+fn colorize(diff: &str) -> String {
+    let mut s: String = "".to_string();
+    for line in diff.split_inclusive('\n') {
+        let style = if line.starts_with("---") || line.starts_with("+++") {
+            BOLD
+        } else if line.starts_with("@@") {
+            CYAN
+        } else if line.starts_with('+') {
+            GREEN
+        } else if line.starts_with('-') {
+            RED
+        } else {
+            ""
+        };
+
+        if style.is_empty() {
+            s += line;
+            continue;
+        }
+        // 改行の前でリセットしておかないと次の行まで色が付く
+        let body = line.trim_end_matches('\n');
+        s += &*format!("{}{}{}{}", style, body, RESET, &line[body.len()..]);
+    }
+    return s;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::diff::unified_diff;
+
+    #[test]
+    fn diff_of_identical_is_empty() {
+        assert_eq!(unified_diff("a.html", "<br />\n", "<br />\n", false), "");
+    }
+
+    #[test]
+    fn diff_shows_changed_lines() {
+        let diff = unified_diff("a.html", "<p>a</p>\n", "<p>\n  a\n</p>\n", false);
+        assert_eq!(
+            diff,
+            "--- a/a.html\n\
+             +++ b/a.html\n\
+             @@ -1 +1,3 @@\n\
+             -<p>a</p>\n\
+             +<p>\n\
+             +  a\n\
+             +</p>\n"
+        );
+    }
+
+    #[test]
+    fn diff_colors_lines() {
+        let diff = unified_diff("a.html", "<p>a</p>\n", "<p>\n  a\n</p>\n", true);
+        assert!(diff.contains("\x1b[31m-<p>a</p>\x1b[0m\n"));
+        assert!(diff.contains("\x1b[32m+  a\x1b[0m\n"));
+    }
+}
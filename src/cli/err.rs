@@ -0,0 +1,35 @@
+use htmlfmt::format::err::ConfigError;
+use htmlfmt::parse::err::ParseError;
+use htmlfmt::tokenize::position::Span;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("{name}: {source}")]
+    Io {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("{}: {source}", location(name, source.span()))]
+    Parse {
+        name: String,
+        source: Box<ParseError>,
+    },
+    #[error("{source}")]
+    Config { source: ConfigError },
+    #[error("invalid glob: {source}")]
+    Glob { source: globset::Error },
+    #[error("{source}")]
+    Walk { source: ignore::Error },
+    #[error("{name}: internal error: {message}")]
+    Panic { name: String, message: String },
+}
+
+/// `name:line:col` for where `span` starts, with the column counted from 1
+/// in chars as editors do; just `name` when there is no span.
+pub fn location(name: &str, span: Option<&Span>) -> String {
+    return match span {
+        Some(span) => format!("{}:{}:{}", name, span.start.line_no, span.start.at_line + 1),
+        None => name.to_string(),
+    };
+}
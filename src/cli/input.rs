@@ -0,0 +1,40 @@
+use crate::cli::err::CliError;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    pub fn from_path(path: &Path) -> Input {
+        if path == Path::new("-") {
+            return Input::Stdin;
+        }
+        return Input::File(path.to_path_buf());
+    }
+
+    pub fn name(&self) -> String {
+        return match self {
+            Input::Stdin => "<stdin>".to_string(),
+            Input::File(path) => path.display().to_string(),
+        };
+    }
+
+    pub fn read(&self) -> Result<String, CliError> {
+        let result = match self {
+            Input::Stdin => {
+                let mut source: String = "".to_string();
+                std::io::stdin().read_to_string(&mut source).map(|_| source)
+            }
+            Input::File(path) => std::fs::read_to_string(path),
+        };
+
+        return result.map_err(|source| CliError::Io {
+            name: self.name(),
+            source,
+        });
+    }
+}
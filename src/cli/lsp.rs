@@ -0,0 +1,478 @@
+use crate::cli::args::Args;
+use crate::cli::input::Input;
+use crate::cli::resolve::Resolver;
+use crate::cli::status::Status;
+use htmlfmt::format::config::{Config, IndentStyle};
+use htmlfmt::format::formatter::Formatter;
+use htmlfmt::parse::err::ParseError;
+use htmlfmt::parse::parser::Parser;
+use htmlfmt::tokenize::tokenizer::Tokenizer;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{Formatting, RangeFormatting, Request as RequestTrait};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, DocumentRangeFormattingParams,
+    FormattingOptions, OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+/// LSP position (line, UTF-16 column) of the byte `offset` in `text`.
+pub fn offset_to_position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    return Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    );
+}
+
+/// Byte offset of the LSP `position` in `text`. Positions past the end of a
+/// line clamp to the end of that line.
+pub fn position_to_offset(text: &str, position: Position) -> usize {
+    let mut line_start: usize = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut column: u32 = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if column >= position.character || c == '\n' {
+            return line_start + i;
+        }
+        column += c.len_utf16() as u32;
+    }
+    return text.len();
+}
+
+/// Single edit turning `old` into `new`, covering only the part in between
+/// their common prefix and suffix so the editor keeps cursors and folds.
+pub fn minimal_edit(old: &str, new: &str) -> Option<TextEdit> {
+    if old == new {
+        return None;
+    }
+
+    let mut prefix: usize = 0;
+    for ((i, a), b) in old.char_indices().zip(new.chars()) {
+        if a != b {
+            break;
+        }
+        prefix = i + a.len_utf8();
+    }
+    let mut suffix: usize = 0;
+    for (a, b) in old[prefix..].chars().rev().zip(new[prefix..].chars().rev()) {
+        if a != b {
+            break;
+        }
+        suffix += a.len_utf8();
+    }
+
+    return Some(TextEdit::new(
+        Range::new(
+            offset_to_position(old, prefix),
+            offset_to_position(old, old.len() - suffix),
+        ),
+        new[prefix..new.len() - suffix].to_string(),
+    ));
+}
+
+/// Diagnostic for a parse error, underlining the offending token.
+pub fn diagnostic(text: &str, err: &ParseError) -> Diagnostic {
+    let range = match err.span() {
+        Some(span) => Range::new(
+            offset_to_position(text, span.start.at_byte),
+            offset_to_position(text, span.end.at_byte),
+        ),
+        None => Range::default(),
+    };
+
+    return Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("htmlfmt".to_string()),
+        message: err.to_string(),
+        ..Diagnostic::default()
+    };
+}
+
+pub struct Server<'a> {
+    resolver: Resolver<'a>,
+    documents: HashMap<Url, String>,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(resolver: Resolver<'a>) -> Server<'a> {
+        return Server {
+            resolver,
+            documents: HashMap::new(),
+        };
+    }
+
+    pub fn capabilities() -> ServerCapabilities {
+        return ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            document_range_formatting_provider: Some(OneOf::Left(true)),
+            ..ServerCapabilities::default()
+        };
+    }
+
+    /// The editor's options are the starting point; `.editorconfig` and
+    /// `htmlfmt.toml` override them, just like on the command line.
+    fn formatter(&self, uri: &Url, options: &FormattingOptions) -> Result<Formatter, String> {
+        let base = Config {
+            ident: options.tab_size as usize,
+            indent_style: if options.insert_spaces {
+                IndentStyle::Space
+            } else {
+                IndentStyle::Tab
+            },
+            insert_final_newline: options
+                .insert_final_newline
+                .unwrap_or(Config::default().insert_final_newline),
+            ..Config::default()
+        };
+        let input = match uri.to_file_path() {
+            Ok(path) => Input::File(path),
+            Err(_) => Input::Stdin,
+        };
+
+        // 編集中に設定ファイルが変わることもあるので、毎回読み直す
+        self.resolver.refresh();
+        return match self.resolver.layered(base, &input) {
+            Ok(config) => Ok(Formatter::new(config)),
+            Err(err) => Err(err.to_string()),
+        };
+    }
+
+    fn diagnostics(&self, uri: &Url) -> Notification {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        if let Some(text) = self.documents.get(uri) {
+            // 最初のエラーだけでなく、見つかったものを全部出す
+            let tokens = Tokenizer::new(text).tokenize();
+            let recovered = Parser::new().parse_recovering(tokens);
+            for diag in &recovered.diagnostics {
+                diagnostics.push(diagnostic(text, &diag.error));
+            }
+        }
+
+        return Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            PublishDiagnosticsParams::new(uri.clone(), diagnostics, None),
+        );
+    }
+
+    /// Handles a notification, returning the diagnostics to publish if the
+    /// document changed.
+    pub fn notify(&mut self, notification: Notification) -> Option<Notification> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    notification.extract(DidOpenTextDocument::METHOD).ok()?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                return Some(self.diagnostics(&uri));
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    notification.extract(DidChangeTextDocument::METHOD).ok()?;
+                let uri = params.text_document.uri;
+                // 全文同期なので最後の変更が今の内容
+                let text = params.content_changes.into_iter().last()?.text;
+                self.documents.insert(uri.clone(), text);
+                return Some(self.diagnostics(&uri));
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    notification.extract(DidCloseTextDocument::METHOD).ok()?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                return Some(self.diagnostics(&uri));
+            }
+            _ => return None,
+        }
+    }
+
+    fn format(
+        &self,
+        uri: &Url,
+        options: &FormattingOptions,
+        range: Option<Range>,
+    ) -> Result<Option<Vec<TextEdit>>, String> {
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return Err(format!("unknown document: {}", uri)),
+        };
+        let formatter = self.formatter(uri, options)?;
+
+        let result = match range {
+            Some(range) => {
+                let start = position_to_offset(text, range.start);
+                let end = position_to_offset(text, range.end);
+                formatter.format_range(text, start..end)
+            }
+            // 全体の整形は、構文エラーがあってもその周りを整形する
+            None => Ok(formatter.format_str_recovering(text).0),
+        };
+        // 構文エラーは診断で伝えているので、ここでは何も変えない
+        return match result {
+            Ok(formatted) => Ok(minimal_edit(text, &formatted).map(|edit| vec![edit])),
+            Err(_) => Ok(None),
+        };
+    }
+
+    pub fn request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            Formatting::METHOD => match request
+                .extract::<DocumentFormattingParams>(Formatting::METHOD)
+            {
+                Ok((_, params)) => self.format(&params.text_document.uri, &params.options, None),
+                Err(err) => Err(err.to_string()),
+            },
+            RangeFormatting::METHOD => {
+                match request.extract::<DocumentRangeFormattingParams>(RangeFormatting::METHOD) {
+                    Ok((_, params)) => self.format(
+                        &params.text_document.uri,
+                        &params.options,
+                        Some(params.range),
+                    ),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported method: {}", method),
+                )
+            }
+        };
+
+        return match result {
+            Ok(edits) => Response::new_ok(id, edits),
+            Err(message) => Response::new_err(id, ErrorCode::RequestFailed as i32, message),
+        };
+    }
+
+    pub fn serve(&mut self, connection: &Connection) -> Result<(), lsp_server::ProtocolError> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.request(request);
+                    if connection.sender.send(response.into()).is_err() {
+                        break;
+                    }
+                }
+                Message::Notification(notification) => {
+                    if let Some(publish) = self.notify(notification) {
+                        if connection.sender.send(publish.into()).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// `htmlfmt lsp`: serves the Language Server Protocol over stdio.
+pub fn run(args: &Args) -> ExitCode {
+    let resolver = match Resolver::new(args) {
+        Ok(resolver) => resolver,
+        Err(err) => {
+            eprintln!("htmlfmt: {}", err);
+            return Status::Error.into();
+        }
+    };
+
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(Server::capabilities()).unwrap();
+    let result = connection
+        .initialize(capabilities)
+        .and_then(|_| Server::new(resolver).serve(&connection));
+    drop(connection);
+
+    if let Err(err) = result {
+        eprintln!("htmlfmt: lsp: {}", err);
+        return Status::Error.into();
+    }
+    if let Err(err) = io_threads.join() {
+        eprintln!("htmlfmt: lsp: {}", err);
+        return Status::Error.into();
+    }
+    return Status::Ok.into();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::args::Args;
+    use crate::cli::lsp::{
+        diagnostic, minimal_edit, offset_to_position, position_to_offset, Server,
+    };
+    use crate::cli::resolve::Resolver;
+    use clap::Parser;
+    use htmlfmt::format::config::Config;
+    use htmlfmt::format::formatter::Formatter;
+    use lsp_server::{Notification, Request, RequestId};
+    use lsp_types::{Position, Range, TextEdit};
+    use serde_json::json;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "ab\n😀x\n";
+        assert_eq!(offset_to_position(text, 3), Position::new(1, 0));
+        assert_eq!(offset_to_position(text, 7), Position::new(1, 2));
+        assert_eq!(position_to_offset(text, Position::new(1, 2)), 7);
+        assert_eq!(position_to_offset(text, Position::new(0, 99)), 2);
+        assert_eq!(position_to_offset(text, Position::new(9, 0)), text.len());
+    }
+
+    #[test]
+    fn minimal_edit_trims_common_ends() {
+        let edit = minimal_edit("<a>\n<b></b>\n</a>\n", "<a>\n  <b></b>\n</a>\n").unwrap();
+        assert_eq!(
+            edit,
+            TextEdit::new(
+                Range::new(Position::new(1, 0), Position::new(1, 0)),
+                "  ".to_string()
+            )
+        );
+        assert_eq!(minimal_edit("same", "same"), None);
+    }
+
+    #[test]
+    fn diagnostic_points_at_closing_tag() {
+        let text = "<div>\n  <p></q>\n</div>";
+        let err = Formatter::new(Config::default())
+            .format_str(text)
+            .unwrap_err();
+        let diagnostic = diagnostic(text, &err);
+        assert_eq!(
+            diagnostic.range,
+            Range::new(Position::new(1, 7), Position::new(1, 8))
+        );
+    }
+
+    #[test]
+    fn diagnostic_counts_utf16_after_multibyte_text() {
+        let text = "<div>\n  <p>日本語😀</q>\n</div>";
+        let err = Formatter::new(Config::default())
+            .format_str(text)
+            .unwrap_err();
+        let diagnostic = diagnostic(text, &err);
+        // 日本語 は 3、😀 は 2 UTF-16 単位
+        assert_eq!(
+            diagnostic.range,
+            Range::new(Position::new(1, 12), Position::new(1, 13))
+        );
+    }
+
+    #[test]
+    fn publishes_every_parse_error() {
+        let args = Args::parse_from(["htmlfmt"]);
+        let mut server = Server::new(Resolver::new(&args).unwrap());
+
+        let publish = server
+            .notify(Notification::new(
+                "textDocument/didOpen".to_string(),
+                json!({"textDocument": {
+                    "uri": "untitled:a.html", "languageId": "html", "version": 1,
+                    "text": "<div>\n  <b>x</q>\n</div>\n</span>"
+                }}),
+            ))
+            .unwrap();
+        let starts: Vec<serde_json::Value> = publish.params["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["range"]["start"].clone())
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                json!({"line": 1, "character": 8}),
+                json!({"line": 2, "character": 2}),
+                json!({"line": 3, "character": 0}),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting_request_returns_edits() {
+        let args = Args::parse_from(["htmlfmt"]);
+        let mut server = Server::new(Resolver::new(&args).unwrap());
+
+        let publish = server
+            .notify(Notification::new(
+                "textDocument/didOpen".to_string(),
+                json!({"textDocument": {
+                    "uri": "untitled:a.html", "languageId": "html", "version": 1,
+                    "text": "<p>a</p>"
+                }}),
+            ))
+            .unwrap();
+        assert_eq!(publish.params["diagnostics"], json!([]));
+
+        let response = server.request(Request::new(
+            RequestId::from(1),
+            "textDocument/formatting".to_string(),
+            json!({
+                "textDocument": {"uri": "untitled:a.html"},
+                "options": {"tabSize": 4, "insertSpaces": true}
+            }),
+        ));
+        assert_eq!(
+            response.result.unwrap(),
+            json!([{
+                "range": {"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 8}},
+                "newText": "\n    a\n</p>\n"
+            }])
+        );
+    }
+
+    #[test]
+    fn formatting_request_formats_around_errors() {
+        let args = Args::parse_from(["htmlfmt"]);
+        let mut server = Server::new(Resolver::new(&args).unwrap());
+
+        server.notify(Notification::new(
+            "textDocument/didOpen".to_string(),
+            json!({"textDocument": {
+                "uri": "untitled:a.html", "languageId": "html", "version": 1,
+                "text": "<div><b>x</b></q></div>\n"
+            }}),
+        ));
+        let response = server.request(Request::new(
+            RequestId::from(1),
+            "textDocument/formatting".to_string(),
+            json!({
+                "textDocument": {"uri": "untitled:a.html"},
+                "options": {"tabSize": 2, "insertSpaces": true}
+            }),
+        ));
+        let edits: Vec<TextEdit> = serde_json::from_value(response.result.unwrap()).unwrap();
+        let mut text = "<div><b>x</b></q></div>\n".to_string();
+        for edit in edits.iter().rev() {
+            let start = position_to_offset(&text, edit.range.start);
+            let end = position_to_offset(&text, edit.range.end);
+            text.replace_range(start..end, &edit.new_text);
+        }
+        assert_eq!(text, "<div>\n  <b>\n    x\n  </b>\n  </q>\n</div>\n");
+    }
+}
@@ -0,0 +1,142 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// `work` panicked on an item instead of returning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panicked {
+    pub message: String,
+}
+
+impl Panicked {
+    fn new(payload: Box<dyn Any + Send>) -> Panicked {
+        // panic! に渡されるのはたいてい &str か String
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+        return Panicked { message };
+    }
+}
+
+/// Number of workers used when `--jobs` is not given.
+pub fn default_jobs() -> usize {
+    return thread::available_parallelism().map_or(1, |n| n.get());
+}
+
+/// Runs `work` over `items` on up to `jobs` threads and hands the results to
+/// `done` on the calling thread, in the same order as `items`. Results are
+/// handed over as soon as every earlier item has finished, so output streams
+/// instead of waiting for the whole batch.
+///
+/// An item on which `work` panics is handed over as `Panicked`; the other
+/// items are still worked on.
+pub fn run_ordered<T, R, W, D>(items: Vec<T>, jobs: usize, work: W, mut done: D)
+where
+    T: Send,
+    R: Send,
+    W: Fn(T) -> R + Sync,
+    D: FnMut(Result<R, Panicked>),
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let (tx, rx) = mpsc::channel::<(usize, Result<R, Panicked>)>();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let tx = tx.clone();
+            let queue = &queue;
+            let work = &work;
+            scope.spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let (i, item) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                // work は共有の状態を持たないので、パニックしても次の要素に進める
+                let result = catch_unwind(AssertUnwindSafe(|| work(item))).map_err(Panicked::new);
+                if tx.send((i, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        // 全ワーカーが終わったら rx が閉じるようにする
+        drop(tx);
+
+        let mut pending: BTreeMap<usize, Result<R, Panicked>> = BTreeMap::new();
+        let mut next: usize = 0;
+        for (i, result) in rx {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&next) {
+                done(result);
+                next += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::pool::{run_ordered, Panicked};
+    use std::time::Duration;
+
+    #[test]
+    fn results_keep_input_order() {
+        let items: Vec<u64> = (0..32).collect();
+        let mut results: Vec<u64> = vec![];
+
+        run_ordered(
+            items,
+            8,
+            |i| {
+                // 後ろの要素ほど早く終わるようにする
+                std::thread::sleep(Duration::from_millis(32 - i));
+                i * 2
+            },
+            |r| results.push(r.unwrap()),
+        );
+
+        assert_eq!(results, (0..32).map(|i| i * 2).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn empty_input_finishes() {
+        let mut count = 0;
+        run_ordered(Vec::<u8>::new(), 4, |i| i, |_| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn a_panic_only_fails_its_item() {
+        let mut results: Vec<Result<u8, Panicked>> = vec![];
+        run_ordered(
+            vec![1, 2, 3, 4],
+            2,
+            |i| {
+                if i == 2 {
+                    panic!("bad item {}", i);
+                }
+                i
+            },
+            |r| results.push(r),
+        );
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(1),
+                Err(Panicked {
+                    message: "bad item 2".to_string()
+                }),
+                Ok(3),
+                Ok(4),
+            ]
+        );
+    }
+}
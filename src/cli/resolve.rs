@@ -0,0 +1,288 @@
+use crate::cli::args::Args;
+use crate::cli::err::CliError;
+use crate::cli::input::Input;
+use htmlfmt::format::config::{find_config, Config, ConfigFile};
+use htmlfmt::format::editorconfig::{apply_editorconfigs, EditorConfig, EDITORCONFIG_FILENAME};
+use htmlfmt::format::formatter::Formatter;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The settings files that apply to the files of one directory.
+#[derive(Default)]
+struct DirSettings {
+    // 近いものから順に
+    editorconfigs: Vec<Arc<EditorConfig>>,
+    config_file: Option<Arc<ConfigFile>>,
+}
+
+/// Reads `path` through `cache`, so each file is only read once.
+fn cached<T>(
+    cache: &Mutex<HashMap<PathBuf, Arc<T>>>,
+    path: &Path,
+    load: impl FnOnce(&Path) -> Result<T, CliError>,
+) -> Result<Arc<T>, CliError> {
+    if let Some(found) = cache.lock().unwrap().get(path) {
+        return Ok(found.clone());
+    }
+    let loaded = Arc::new(load(path)?);
+    cache
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), loaded.clone());
+    return Ok(loaded);
+}
+
+/// Picks the formatter for each input. Settings are layered, later ones
+/// winning: defaults, `.editorconfig`, the `--config` file or else the nearest
+/// `htmlfmt.toml`, and finally the command-line options.
+///
+/// Every settings file is read once, and inputs that end up with the same
+/// settings share one formatter.
+pub struct Resolver<'a> {
+    args: &'a Args,
+    explicit: Option<Arc<ConfigFile>>,
+    dirs: Mutex<HashMap<PathBuf, Arc<DirSettings>>>,
+    editorconfigs: Mutex<HashMap<PathBuf, Arc<EditorConfig>>>,
+    config_files: Mutex<HashMap<PathBuf, Arc<ConfigFile>>>,
+    formatters: Mutex<Vec<Arc<Formatter>>>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Fails early when the `--config` file can't be read, instead of
+    /// reporting the same error for every input.
+    pub fn new(args: &'a Args) -> Result<Resolver<'a>, CliError> {
+        let explicit = match &args.config {
+            Some(path) => Some(Arc::new(
+                ConfigFile::load(path).map_err(|source| CliError::Config { source })?,
+            )),
+            None => None,
+        };
+        return Ok(Resolver {
+            args,
+            explicit,
+            dirs: Mutex::new(HashMap::new()),
+            editorconfigs: Mutex::new(HashMap::new()),
+            config_files: Mutex::new(HashMap::new()),
+            formatters: Mutex::new(vec![]),
+        });
+    }
+
+    /// Forgets the `.editorconfig` and `htmlfmt.toml` files read so far, so
+    /// that edits to them are picked up. The `--config` file is kept.
+    pub fn refresh(&self) {
+        self.dirs.lock().unwrap().clear();
+        self.editorconfigs.lock().unwrap().clear();
+        self.config_files.lock().unwrap().clear();
+        self.formatters.lock().unwrap().clear();
+    }
+
+    fn search_dir(input: &Input) -> Option<PathBuf> {
+        return match input {
+            Input::Stdin => std::env::current_dir().ok(),
+            Input::File(path) => std::path::absolute(path)
+                .ok()
+                .and_then(|p| p.parent().map(|p| p.to_path_buf())),
+        };
+    }
+
+    fn dir_settings(&self, dir: &Path) -> Result<Arc<DirSettings>, CliError> {
+        if let Some(found) = self.dirs.lock().unwrap().get(dir) {
+            return Ok(found.clone());
+        }
+
+        let mut settings = DirSettings::default();
+        for ancestor in dir.ancestors() {
+            let candidate = ancestor.join(EDITORCONFIG_FILENAME);
+            if !candidate.is_file() {
+                continue;
+            }
+            let editorconfig = cached(&self.editorconfigs, &candidate, |path| {
+                return EditorConfig::load(path).map_err(|source| CliError::Config { source });
+            })?;
+            let root = editorconfig.is_root();
+            settings.editorconfigs.push(editorconfig);
+            if root {
+                break;
+            }
+        }
+        // --config があれば htmlfmt.toml は探さない
+        if self.explicit.is_none() {
+            if let Some(path) = find_config(dir) {
+                settings.config_file = Some(cached(&self.config_files, &path, |path| {
+                    return ConfigFile::load(path).map_err(|source| CliError::Config { source });
+                })?);
+            }
+        }
+
+        let settings = Arc::new(settings);
+        self.dirs
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), settings.clone());
+        return Ok(settings);
+    }
+
+    pub fn config(&self, input: &Input) -> Result<Config, CliError> {
+        return self.layered(Config::default(), input);
+    }
+
+    /// Like [`Resolver::config`], but starting from `config` instead of the
+    /// defaults.
+    pub fn layered(&self, mut config: Config, input: &Input) -> Result<Config, CliError> {
+        let settings = match Self::search_dir(input) {
+            Some(dir) => self.dir_settings(&dir)?,
+            None => Arc::new(DirSettings::default()),
+        };
+
+        // 標準入力にはファイル名がないので .editorconfig は使わない
+        if let Input::File(path) = input {
+            if let Ok(path) = std::path::absolute(path) {
+                let found: Vec<&EditorConfig> =
+                    settings.editorconfigs.iter().map(|e| e.as_ref()).collect();
+                apply_editorconfigs(&found, &path, &mut config);
+            }
+        }
+
+        if let Some(file) = self.explicit.as_ref().or(settings.config_file.as_ref()) {
+            config = file
+                .apply(&config)
+                .map_err(|source| CliError::Config { source })?;
+        }
+
+        self.args.apply(&mut config);
+        return Ok(config);
+    }
+
+    pub fn formatter(&self, input: &Input) -> Result<Arc<Formatter>, CliError> {
+        let config = self.config(input)?;
+
+        let mut formatters = self.formatters.lock().unwrap();
+        if let Some(found) = formatters.iter().find(|f| f.config() == &config) {
+            return Ok(found.clone());
+        }
+        let formatter = Arc::new(Formatter::new(config));
+        formatters.push(formatter.clone());
+        return Ok(formatter);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::args::Args;
+    use crate::cli::input::Input;
+    use crate::cli::resolve::Resolver;
+    use clap::Parser;
+    use std::fs;
+    use std::sync::Arc;
+
+    fn indent_of(resolver: &Resolver, input: &Input) -> String {
+        let formatter = resolver.formatter(input).unwrap();
+        return formatter.format_str("<p>a</p>").unwrap();
+    }
+
+    #[test]
+    fn nearest_config_is_used() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("htmlfmt.toml"), "indent = 4\n").unwrap();
+        fs::write(dir.path().join("sub/htmlfmt.toml"), "indent = 1\n").unwrap();
+
+        let args = Args::parse_from(["htmlfmt"]);
+        let resolver = Resolver::new(&args).unwrap();
+
+        let top = Input::File(dir.path().join("index.html"));
+        let sub = Input::File(dir.path().join("sub/index.html"));
+        assert_eq!(indent_of(&resolver, &top), "<p>\n    a\n</p>\n");
+        assert_eq!(indent_of(&resolver, &sub), "<p>\n a\n</p>\n");
+    }
+
+    #[test]
+    fn command_line_wins_over_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("custom.toml");
+        fs::write(&config, "indent = 4\n").unwrap();
+
+        let args = Args::parse_from([
+            "htmlfmt",
+            "--config",
+            config.to_str().unwrap(),
+            "--indent",
+            "3",
+        ]);
+        let resolver = Resolver::new(&args).unwrap();
+
+        let input = Input::File(dir.path().join("index.html"));
+        assert_eq!(indent_of(&resolver, &input), "<p>\n   a\n</p>\n");
+    }
+
+    #[test]
+    fn htmlfmt_toml_wins_over_editorconfig() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(
+            dir.path().join(".editorconfig"),
+            "root = true\n[*.html]\nindent_size = 8\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("htmlfmt.toml"), "indent = 4\n").unwrap();
+
+        let args = Args::parse_from(["htmlfmt"]);
+        let resolver = Resolver::new(&args).unwrap();
+
+        let input = Input::File(dir.path().join("index.html"));
+        assert_eq!(indent_of(&resolver, &input), "<p>\r\n    a\r\n</p>\r\n");
+    }
+
+    #[test]
+    fn formatters_are_shared_and_files_read_once() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(
+            dir.path().join(".editorconfig"),
+            "root = true\n[b.html]\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("htmlfmt.toml"), "indent = 4\n").unwrap();
+
+        let args = Args::parse_from(["htmlfmt"]);
+        let resolver = Resolver::new(&args).unwrap();
+
+        let a = resolver
+            .formatter(&Input::File(dir.path().join("a.html")))
+            .unwrap();
+        let sub = resolver
+            .formatter(&Input::File(dir.path().join("sub/a.html")))
+            .unwrap();
+        assert!(Arc::ptr_eq(&a, &sub));
+
+        // 変えても読み直さない
+        fs::write(dir.path().join("htmlfmt.toml"), "indent = 1\n").unwrap();
+        let again = resolver
+            .formatter(&Input::File(dir.path().join("c.html")))
+            .unwrap();
+        assert!(Arc::ptr_eq(&a, &again));
+
+        // 同じディレクトリでも、ファイルごとの .editorconfig の節は効く
+        let b = resolver
+            .formatter(&Input::File(dir.path().join("b.html")))
+            .unwrap();
+        assert_eq!(
+            b.format_str("<p>a</p>").unwrap(),
+            "<p>\r\n    a\r\n</p>\r\n"
+        );
+        assert!(!Arc::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn broken_explicit_config_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("custom.toml");
+        fs::write(&config, "unknown = true\n").unwrap();
+
+        let args = Args::parse_from(["htmlfmt", "--config", config.to_str().unwrap()]);
+        assert!(Resolver::new(&args).is_err());
+    }
+}
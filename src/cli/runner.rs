@@ -0,0 +1,328 @@
+use crate::cli::args::Args;
+use crate::cli::diff::unified_diff;
+use crate::cli::err::{location, CliError};
+use crate::cli::input::Input;
+use crate::cli::pool::{default_jobs, run_ordered};
+use crate::cli::resolve::Resolver;
+use crate::cli::status::Status;
+use crate::cli::walk::{walk, Filter};
+use crate::cli::write::replace_file;
+use htmlfmt::format::formatter::Formatter;
+use htmlfmt::format::range::line_range;
+use htmlfmt::parse::diagnostic::Diagnostic;
+use std::io::Write;
+use std::process::ExitCode;
+
+pub struct Formatted {
+    pub input: Input,
+    pub source: String,
+    pub formatted: String,
+    // --recover で読み飛ばしたエラー
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Formatted {
+    pub fn changed(&self) -> bool {
+        return self.source != self.formatted;
+    }
+}
+
+/// Expands the paths given on the command line. Files named explicitly are
+/// always formatted; the include/exclude filters only apply to directories.
+pub fn inputs(args: &Args) -> Vec<Result<Input, CliError>> {
+    if args.files.is_empty() {
+        return vec![Ok(Input::Stdin)];
+    }
+
+    let filter = match Filter::new(&args.include, &args.exclude) {
+        Ok(filter) => filter,
+        Err(err) => return vec![Err(err)],
+    };
+
+    let mut inputs: Vec<Result<Input, CliError>> = vec![];
+    for path in &args.files {
+        if path.is_dir() {
+            inputs.extend(walk(path, &filter));
+        } else {
+            inputs.push(Ok(Input::from_path(path)));
+        }
+    }
+    return inputs;
+}
+
+pub fn format_input(
+    args: &Args,
+    formatter: &Formatter,
+    input: Input,
+) -> Result<Formatted, CliError> {
+    let source = input.read()?;
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let result = if let Some((first, last)) = args.range {
+        formatter.format_range(&source, line_range(&source, first, last))
+    } else if let Some((start, end)) = args.byte_range {
+        formatter.format_range(&source, start..end)
+    } else if args.recover {
+        let (formatted, found) = formatter.format_str_recovering(&source);
+        diagnostics = found;
+        Ok(formatted)
+    } else {
+        formatter.format_str(&source)
+    };
+
+    return match result {
+        Ok(formatted) => Ok(Formatted {
+            input,
+            source,
+            formatted,
+            diagnostics,
+        }),
+        Err(err) => Err(CliError::Parse {
+            name: input.name(),
+            source: Box::new(err),
+        }),
+    };
+}
+
+fn emit(args: &Args, result: &Formatted, out: &mut impl Write) -> std::io::Result<Status> {
+    if args.diff {
+        let name = result.input.name();
+        let diff = unified_diff(
+            &name,
+            &result.source,
+            &result.formatted,
+            args.color_enabled(),
+        );
+        out.write_all(diff.as_bytes())?;
+        if args.check && result.changed() {
+            return Ok(Status::Unformatted);
+        }
+        return Ok(Status::Ok);
+    }
+
+    if args.check {
+        if result.changed() {
+            writeln!(out, "{}", result.input.name())?;
+            return Ok(Status::Unformatted);
+        }
+        return Ok(Status::Ok);
+    }
+
+    if args.write {
+        if let Input::File(path) = &result.input {
+            // 変更がなければ触らない (mtime を保つ)
+            if result.changed() {
+                replace_file(path, &result.formatted)?;
+            }
+            return Ok(Status::Ok);
+        }
+    }
+
+    out.write_all(result.formatted.as_bytes())?;
+    return Ok(Status::Ok);
+}
+
+pub fn run(args: &Args) -> ExitCode {
+    let resolver = match Resolver::new(args) {
+        Ok(resolver) => resolver,
+        Err(err) => {
+            eprintln!("htmlfmt: {}", err);
+            return Status::Error.into();
+        }
+    };
+    let jobs = args.jobs.map_or_else(default_jobs, |n| n.get());
+    let mut stdout = std::io::stdout().lock();
+    let mut status = Status::Ok;
+
+    let inputs = inputs(args);
+    // パニックした入力を名前で報告できるよう、結果と同じ順に並べておく
+    let mut names = inputs
+        .iter()
+        .map(|input| match input {
+            Ok(input) => input.name(),
+            Err(err) => err.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .into_iter();
+
+    run_ordered(
+        inputs,
+        jobs,
+        |input| {
+            let input = input?;
+            let formatter = resolver.formatter(&input)?;
+            format_input(args, &formatter, input)
+        },
+        |result| {
+            let name = names.next().unwrap_or_default();
+            let result = match result {
+                Ok(result) => result,
+                Err(panicked) => Err(CliError::Panic {
+                    name,
+                    message: panicked.message,
+                }),
+            };
+            match result {
+                Ok(result) => {
+                    for diagnostic in &result.diagnostics {
+                        eprintln!(
+                            "htmlfmt: {}: warning: {}",
+                            location(&result.input.name(), Some(&diagnostic.span)),
+                            diagnostic.error
+                        );
+                    }
+                    match emit(args, &result, &mut stdout) {
+                        Ok(s) => status = status.merge(s),
+                        Err(err) => {
+                            eprintln!("htmlfmt: {}: {}", result.input.name(), err);
+                            status = status.merge(Status::Error);
+                        }
+                    }
+                }
+                Err(err) => {
+                    eprintln!("htmlfmt: {}", err);
+                    status = status.merge(Status::Error);
+                }
+            }
+        },
+    );
+
+    return status.into();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::args::Args;
+    use crate::cli::input::Input;
+    use crate::cli::runner::{emit, format_input, inputs, Formatted};
+    use crate::cli::status::Status;
+    use clap::Parser;
+    use htmlfmt::format::config::Config;
+    use htmlfmt::format::formatter::Formatter;
+    use std::path::PathBuf;
+
+    #[test]
+    fn inputs_default_to_stdin() {
+        let args = Args::parse_from(["htmlfmt"]);
+        let inputs: Vec<Input> = inputs(&args).into_iter().map(|i| i.unwrap()).collect();
+        assert_eq!(inputs, vec![Input::Stdin]);
+    }
+
+    #[test]
+    fn inputs_dash_is_stdin() {
+        let args = Args::parse_from(["htmlfmt", "a.html", "-"]);
+        let inputs: Vec<Input> = inputs(&args).into_iter().map(|i| i.unwrap()).collect();
+        assert_eq!(
+            inputs,
+            vec![Input::File(PathBuf::from("a.html")), Input::Stdin]
+        );
+    }
+
+    #[test]
+    fn indent_maps_onto_config() {
+        let args = Args::parse_from(["htmlfmt", "--indent", "4"]);
+        let mut config = Config::default();
+        args.apply(&mut config);
+        assert_eq!(config.ident, 4);
+    }
+
+    #[test]
+    fn range_is_parsed_as_start_end() {
+        let args = Args::parse_from(["htmlfmt", "--range", "3:5"]);
+        assert_eq!(args.range, Some((3, 5)));
+        assert!(Args::try_parse_from(["htmlfmt", "--range", "5:3"]).is_err());
+        assert!(Args::try_parse_from(["htmlfmt", "--range", "5"]).is_err());
+    }
+
+    #[test]
+    fn check_lists_changed_files() {
+        let args = Args::parse_from(["htmlfmt", "--check"]);
+        let changed = Formatted {
+            input: Input::File(PathBuf::from("a.html")),
+            source: "<p>a</p>".to_string(),
+            formatted: "<p>\n  a\n</p>\n".to_string(),
+            diagnostics: vec![],
+        };
+        let unchanged = Formatted {
+            input: Input::File(PathBuf::from("b.html")),
+            source: "<br />\n".to_string(),
+            formatted: "<br />\n".to_string(),
+            diagnostics: vec![],
+        };
+
+        let mut out: Vec<u8> = vec![];
+        assert_eq!(
+            emit(&args, &changed, &mut out).unwrap(),
+            Status::Unformatted
+        );
+        assert_eq!(emit(&args, &unchanged, &mut out).unwrap(), Status::Ok);
+        assert_eq!(String::from_utf8(out).unwrap(), "a.html\n");
+    }
+
+    #[test]
+    fn write_skips_unchanged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        std::fs::write(&path, "<br />\n").unwrap();
+        let before = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let args = Args::parse_from(["htmlfmt", "--write"]);
+        let result = Formatted {
+            input: Input::File(path.clone()),
+            source: "<br />\n".to_string(),
+            formatted: "<br />\n".to_string(),
+            diagnostics: vec![],
+        };
+        let mut out: Vec<u8> = vec![];
+        assert_eq!(emit(&args, &result, &mut out).unwrap(), Status::Ok);
+
+        let after = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(before, after);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn recover_formats_around_parse_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        std::fs::write(&path, "</div><p>x</p>").unwrap();
+        let formatter = Formatter::new(Config::default());
+
+        let args = Args::parse_from(["htmlfmt"]);
+        let err = match format_input(&args, &formatter, Input::File(path.clone())) {
+            Ok(_) => panic!("expected a parse error"),
+            Err(err) => err,
+        };
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:1:1: unexpected token (expect: Eof, found EndTag)",
+                path.display()
+            )
+        );
+
+        let args = Args::parse_from(["htmlfmt", "--recover"]);
+        let result = format_input(&args, &formatter, Input::File(path)).unwrap();
+        assert_eq!(result.formatted, "</div>\n<p>\n  x\n</p>\n");
+        assert_eq!(result.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn check_with_diff_prints_diff() {
+        let args = Args::parse_from(["htmlfmt", "--check", "--diff", "--color", "never"]);
+        let changed = Formatted {
+            input: Input::File(PathBuf::from("a.html")),
+            source: "<p>a</p>\n".to_string(),
+            formatted: "<p>\n  a\n</p>\n".to_string(),
+            diagnostics: vec![],
+        };
+
+        let mut out: Vec<u8> = vec![];
+        assert_eq!(
+            emit(&args, &changed, &mut out).unwrap(),
+            Status::Unformatted
+        );
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("--- a/a.html\n+++ b/a.html\n"));
+    }
+}
@@ -0,0 +1,37 @@
+use std::process::ExitCode;
+
+/// Exit status of a run. When several files end up in different states the
+/// most severe one wins, so the variants are ordered by severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// Every file is formatted (or was formatted successfully).
+    Ok = 0,
+    /// `--check`: at least one file would be reformatted.
+    Unformatted = 1,
+    /// At least one file could not be read or failed to parse.
+    Error = 2,
+}
+
+impl Status {
+    pub fn merge(self, other: Status) -> Status {
+        return self.max(other);
+    }
+}
+
+impl From<Status> for ExitCode {
+    fn from(status: Status) -> Self {
+        return ExitCode::from(status as u8);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::status::Status;
+
+    #[test]
+    fn merge_keeps_most_severe() {
+        assert_eq!(Status::Ok.merge(Status::Unformatted), Status::Unformatted);
+        assert_eq!(Status::Error.merge(Status::Unformatted), Status::Error);
+        assert_eq!(Status::Ok.merge(Status::Ok), Status::Ok);
+    }
+}
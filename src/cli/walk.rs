@@ -0,0 +1,169 @@
+use crate::cli::err::CliError;
+use crate::cli::input::Input;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::path::Path;
+
+/// Globs a directory walk picks up when no `--include` is given.
+pub const DEFAULT_INCLUDE: [&str; 2] = ["*.html", "*.htm"];
+
+/// Gitignore-style file listing paths the walk skips.
+pub const IGNORE_FILENAME: &str = ".htmlfmtignore";
+
+/// `--include`/`--exclude` globs, matched against paths relative to the
+/// directory being walked. `*` also matches `/`, so `*.html` picks files at
+/// any depth.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet, CliError> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        match Glob::new(glob) {
+            Ok(g) => builder.add(g),
+            Err(source) => return Err(CliError::Glob { source }),
+        };
+    }
+    return builder.build().map_err(|source| CliError::Glob { source });
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Filter, CliError> {
+        let include = if include.is_empty() {
+            let defaults: Vec<String> = DEFAULT_INCLUDE.iter().map(|g| g.to_string()).collect();
+            glob_set(&defaults)?
+        } else {
+            glob_set(include)?
+        };
+
+        return Ok(Filter {
+            include,
+            exclude: glob_set(exclude)?,
+        });
+    }
+
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        return self.exclude.is_match(relative);
+    }
+
+    pub fn matches(&self, relative: &Path) -> bool {
+        return self.include.is_match(relative) && !self.is_excluded(relative);
+    }
+}
+
+/// Recursively collects the files under `root` that pass `filter` and are not
+/// listed in a `.htmlfmtignore`, sorted by path.
+pub fn walk(root: &Path, filter: &Filter) -> Vec<Result<Input, CliError>> {
+    let prune = filter.clone();
+    let prune_root = root.to_path_buf();
+
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(true)
+        .parents(true)
+        .add_custom_ignore_filename(IGNORE_FILENAME)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| match entry.path().strip_prefix(&prune_root) {
+            Ok(relative) if entry.depth() > 0 => !prune.is_excluded(relative),
+            _ => true,
+        })
+        .build();
+
+    let mut inputs: Vec<Result<Input, CliError>> = vec![];
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(source) => {
+                inputs.push(Err(CliError::Walk { source }));
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if filter.matches(relative) {
+            inputs.push(Ok(Input::File(entry.into_path())));
+        }
+    }
+
+    return inputs;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::input::Input;
+    use crate::cli::walk::{walk, Filter};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn touch(root: &Path, relative: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+
+    fn walked(root: &Path, filter: &Filter) -> Vec<PathBuf> {
+        return walk(root, filter)
+            .into_iter()
+            .map(|input| match input.unwrap() {
+                Input::File(path) => path.strip_prefix(root).unwrap().to_path_buf(),
+                Input::Stdin => panic!("walk yielded stdin"),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn walk_picks_html_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), "index.html");
+        touch(dir.path(), "a/b/page.htm");
+        touch(dir.path(), "a/style.css");
+        touch(dir.path(), ".hidden/x.html");
+
+        let filter = Filter::new(&[], &[]).unwrap();
+        assert_eq!(
+            walked(dir.path(), &filter),
+            vec![PathBuf::from("a/b/page.htm"), PathBuf::from("index.html")]
+        );
+    }
+
+    #[test]
+    fn walk_applies_include_and_exclude() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), "index.html");
+        touch(dir.path(), "page.tmpl");
+        touch(dir.path(), "vendor/lib.tmpl");
+
+        let filter = Filter::new(
+            &["*.tmpl".to_string(), "*.html".to_string()],
+            &["vendor".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            walked(dir.path(), &filter),
+            vec![PathBuf::from("index.html"), PathBuf::from("page.tmpl")]
+        );
+    }
+
+    #[test]
+    fn walk_honours_htmlfmtignore() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), "index.html");
+        touch(dir.path(), "generated/out.html");
+        touch(dir.path(), "a/keep.html");
+        touch(dir.path(), "a/skip.html");
+        fs::write(dir.path().join(".htmlfmtignore"), "generated/\n").unwrap();
+        fs::write(dir.path().join("a/.htmlfmtignore"), "skip.html\n").unwrap();
+
+        let filter = Filter::new(&[], &[]).unwrap();
+        assert_eq!(
+            walked(dir.path(), &filter),
+            vec![PathBuf::from("a/keep.html"), PathBuf::from("index.html")]
+        );
+    }
+}
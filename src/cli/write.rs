@@ -0,0 +1,62 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// Replaces the contents of `path` atomically.
+///
+/// The new contents are written to a temporary file next to the original and
+/// then renamed over it, so readers never see a half-written file. The
+/// original permissions are kept, and symlinks are followed so the link
+/// itself stays in place.
+pub fn replace_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    let target = fs::canonicalize(path)?;
+    let permissions = fs::metadata(&target)?.permissions();
+    let dir = match target.parent() {
+        Some(dir) => dir,
+        None => Path::new("."),
+    };
+
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(contents.as_bytes())?;
+    tmp.as_file().sync_all()?;
+    fs::set_permissions(tmp.path(), permissions)?;
+    tmp.persist(&target).map_err(|err| err.error)?;
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::write::replace_file;
+    use std::fs;
+
+    #[test]
+    fn replace_file_overwrites_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, "<p>a</p>").unwrap();
+
+        replace_file(&path, "<p>\n  a\n</p>\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<p>\n  a\n</p>\n");
+        // 一時ファイルは残らない
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_file_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, "<p>a</p>").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        replace_file(&path, "<p>\n  a\n</p>\n").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+}
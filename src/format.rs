@@ -1,2 +1,5 @@
 pub mod config;
+pub mod editorconfig;
+pub mod err;
 pub mod formatter;
+pub mod range;
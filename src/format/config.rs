@@ -1,10 +1,276 @@
-#[derive(Default)]
+use crate::format::err::ConfigError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Name of the config file looked up next to the formatted files.
+pub const CONFIG_FILENAME: &str = "htmlfmt.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+    Space,
+    Tab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndOfLine {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl EndOfLine {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            EndOfLine::Lf => "\n",
+            EndOfLine::Crlf => "\r\n",
+            EndOfLine::Cr => "\r",
+        };
+    }
+}
+
+/// How character references such as `&nbsp;` are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharRefStyle {
+    /// As they were written.
+    Keep,
+    /// `&nbsp;`, where the character has a name.
+    Named,
+    /// `&#160;`
+    Decimal,
+    /// `&#xA0;`
+    Hex,
+    /// The character itself, unless it is markup, whitespace or invisible.
+    Literal,
+}
+
+/// Which quotes attribute values are written with. Values containing that
+/// quote fall back to the other one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeQuotes {
+    Double,
+    Single,
+}
+
+/// How boolean attributes such as `disabled` are written when set.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BooleanAttributes {
+    /// `disabled`
+    Bare,
+    /// `disabled=""`
+    Empty,
+    /// `disabled="disabled"`
+    Name,
+}
+
+/// How void elements such as `<br>` are written. Other elements written
+/// as `<x />` always keep their slash.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoidStyle {
+    /// `<br>`
+    Html,
+    /// `<br />`
+    Xhtml,
+}
+
+/// What to do with end tags the source left out, like `</li>` in
+/// `<li>a<li>b`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OmittedEndTags {
+    Keep,
+    Insert,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
+    /// Number of spaces per indentation level (`indent` in htmlfmt.toml).
+    /// Ignored when indenting with tabs.
+    #[serde(rename = "indent")]
     pub ident: usize,
+    pub indent_style: IndentStyle,
+    pub end_of_line: EndOfLine,
+    pub insert_final_newline: bool,
+    pub char_refs: CharRefStyle,
+    pub attribute_quotes: AttributeQuotes,
+    pub boolean_attributes: BooleanAttributes,
+    pub void_style: VoidStyle,
+    pub omitted_end_tags: OmittedEndTags,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ident: 2,
+            indent_style: IndentStyle::Space,
+            end_of_line: EndOfLine::Lf,
+            insert_final_newline: true,
+            char_refs: CharRefStyle::Keep,
+            attribute_quotes: AttributeQuotes::Double,
+            boolean_attributes: BooleanAttributes::Bare,
+            void_style: VoidStyle::Xhtml,
+            omitted_end_tags: OmittedEndTags::Keep,
+        }
+    }
 }
 
 impl Config {
-    pub fn default() -> Self {
-        Self { ident: 2 }
+    /// Reads an `htmlfmt.toml`. Missing keys keep their default value and
+    /// unknown keys are rejected.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        return Config::default().layer(path);
+    }
+
+    /// Reads an `htmlfmt.toml` and applies the keys it sets on top of `self`.
+    /// Keys the file doesn't mention keep the value they have in `self`.
+    pub fn layer(&self, path: &Path) -> Result<Config, ConfigError> {
+        return ConfigFile::load(path)?.apply(self);
+    }
+}
+
+/// An `htmlfmt.toml` read once, to be applied on top of any number of
+/// configs.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    // ファイルに書かれていたキーだけ
+    table: toml::Table,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<ConfigFile, ConfigError> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(source) => {
+                return Err(ConfigError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+        let toml_error = |source| ConfigError::Toml {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        // 先に Config として読んでおくと、未知のキーや型の間違いを
+        // 行番号付きで報告できる
+        toml::from_str::<Config>(&source).map_err(toml_error)?;
+
+        return Ok(ConfigFile {
+            path: path.to_path_buf(),
+            table: toml::from_str::<toml::Table>(&source).map_err(toml_error)?,
+        });
+    }
+
+    /// `base` with the keys this file sets replaced.
+    pub fn apply(&self, base: &Config) -> Result<Config, ConfigError> {
+        let mut table = toml::Table::try_from(base).unwrap();
+        table.extend(self.table.clone());
+        return table.try_into().map_err(|source| ConfigError::Toml {
+            path: self.path.clone(),
+            source,
+        });
+    }
+}
+
+/// Looks for an `htmlfmt.toml` in `dir` and its ancestors. The search stops
+/// at the root of the repository (the first directory holding `.git`), so a
+/// config outside of the project is never picked up by accident.
+pub fn find_config(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let candidate = ancestor.join(CONFIG_FILENAME);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if ancestor.join(".git").exists() {
+            break;
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::format::config::{
+        find_config, AttributeQuotes, BooleanAttributes, Config, EndOfLine, IndentStyle,
+        CONFIG_FILENAME,
+    };
+    use crate::format::err::ConfigError;
+    use std::fs;
+
+    #[test]
+    fn load_reads_indent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        fs::write(&path, "indent = 4\n").unwrap();
+
+        assert_eq!(Config::load(&path).unwrap().ident, 4);
+    }
+
+    #[test]
+    fn load_reads_attribute_styles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        fs::write(
+            &path,
+            "attribute_quotes = \"single\"\nboolean_attributes = \"name\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.attribute_quotes, AttributeQuotes::Single);
+        assert_eq!(config.boolean_attributes, BooleanAttributes::Name);
+    }
+
+    #[test]
+    fn layer_keeps_unset_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        fs::write(&path, "end_of_line = \"crlf\"\n").unwrap();
+
+        let base = Config {
+            indent_style: IndentStyle::Tab,
+            ..Config::default()
+        };
+        let config = base.layer(&path).unwrap();
+        assert_eq!(config.indent_style, IndentStyle::Tab);
+        assert_eq!(config.end_of_line, EndOfLine::Crlf);
+    }
+
+    #[test]
+    fn load_rejects_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        fs::write(&path, "indnet = 4\n").unwrap();
+
+        match Config::load(&path) {
+            Err(err @ ConfigError::Toml { .. }) => {
+                assert!(err.to_string().contains("unknown field `indnet`"))
+            }
+            other => panic!("expected a toml error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn find_config_walks_up_to_repo_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("a/b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+
+        // リポジトリの外にある設定は拾わない
+        fs::write(dir.path().join(CONFIG_FILENAME), "").unwrap();
+        assert_eq!(find_config(&nested), None);
+
+        fs::write(repo.join(CONFIG_FILENAME), "").unwrap();
+        assert_eq!(find_config(&nested), Some(repo.join(CONFIG_FILENAME)));
     }
 }
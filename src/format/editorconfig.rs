@@ -0,0 +1,247 @@
+use crate::format::config::{Config, EndOfLine, IndentStyle};
+use crate::format::err::ConfigError;
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::{Path, PathBuf};
+
+pub const EDITORCONFIG_FILENAME: &str = ".editorconfig";
+
+struct Section {
+    matcher: Option<GlobMatcher>,
+    properties: Vec<(String, String)>,
+}
+
+/// One parsed `.editorconfig` file.
+pub struct EditorConfig {
+    dir: PathBuf,
+    root: bool,
+    sections: Vec<Section>,
+}
+
+/// Turns a section name into a glob relative to the directory holding the
+/// `.editorconfig`. Names without a `/` match at any depth.
+fn section_glob(name: &str) -> Option<GlobMatcher> {
+    let pattern = if let Some(stripped) = name.strip_prefix('/') {
+        stripped.to_string()
+    } else if name.contains('/') {
+        name.to_string()
+    } else {
+        format!("**/{}", name)
+    };
+
+    return GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .ok()
+        .map(|g| g.compile_matcher());
+}
+
+impl EditorConfig {
+    pub fn parse(dir: &Path, source: &str) -> EditorConfig {
+        let mut root = false;
+        let mut sections: Vec<Section> = vec![];
+
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                sections.push(Section {
+                    matcher: section_glob(&line[1..line.len() - 1]),
+                    properties: vec![],
+                });
+                continue;
+            }
+
+            // 読めない行は仕様どおり無視する
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_lowercase()),
+                None => continue,
+            };
+            match sections.last_mut() {
+                Some(section) => section.properties.push((key, value)),
+                None => {
+                    if key == "root" {
+                        root = value == "true";
+                    }
+                }
+            }
+        }
+
+        return EditorConfig {
+            dir: dir.to_path_buf(),
+            root,
+            sections,
+        };
+    }
+
+    pub fn load(path: &Path) -> Result<EditorConfig, ConfigError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        return match std::fs::read_to_string(path) {
+            Ok(source) => Ok(EditorConfig::parse(dir, &source)),
+            Err(source) => Err(ConfigError::Io {
+                path: path.to_path_buf(),
+                source,
+            }),
+        };
+    }
+
+    pub fn is_root(&self) -> bool {
+        return self.root;
+    }
+
+    /// Properties of every section matching `file`, in file order.
+    pub fn properties(&self, file: &Path) -> Vec<(String, String)> {
+        let relative = match file.strip_prefix(&self.dir) {
+            Ok(relative) => relative,
+            Err(_) => return vec![],
+        };
+
+        let mut properties: Vec<(String, String)> = vec![];
+        for section in &self.sections {
+            if let Some(matcher) = &section.matcher {
+                if matcher.is_match(relative) {
+                    properties.extend(section.properties.iter().cloned());
+                }
+            }
+        }
+        return properties;
+    }
+}
+
+fn apply_property(config: &mut Config, key: &str, value: &str) {
+    let default = Config::default();
+    match (key, value) {
+        ("indent_style", "space") => config.indent_style = IndentStyle::Space,
+        ("indent_style", "tab") => config.indent_style = IndentStyle::Tab,
+        ("indent_style", "unset") => config.indent_style = default.indent_style,
+        ("indent_size", "unset") => config.ident = default.ident,
+        ("indent_size", size) => {
+            // "tab" は tab_width に従うが、タブで字下げするなら幅は関係ない
+            if let Ok(size) = size.parse() {
+                config.ident = size;
+            }
+        }
+        ("end_of_line", "lf") => config.end_of_line = EndOfLine::Lf,
+        ("end_of_line", "crlf") => config.end_of_line = EndOfLine::Crlf,
+        ("end_of_line", "cr") => config.end_of_line = EndOfLine::Cr,
+        ("end_of_line", "unset") => config.end_of_line = default.end_of_line,
+        ("insert_final_newline", "true") => config.insert_final_newline = true,
+        ("insert_final_newline", "false") => config.insert_final_newline = false,
+        ("insert_final_newline", "unset") => {
+            config.insert_final_newline = default.insert_final_newline
+        }
+        _ => {}
+    }
+}
+
+/// Applies the `.editorconfig` files that govern `file` to `config`. Files
+/// are looked up from the directory of `file` upwards until one declares
+/// `root = true`; nearer files and later sections win.
+pub fn apply_editorconfig(file: &Path, config: &mut Config) -> Result<(), ConfigError> {
+    let file = match std::path::absolute(file) {
+        Ok(file) => file,
+        Err(_) => return Ok(()),
+    };
+
+    let mut found: Vec<EditorConfig> = vec![];
+    for dir in file.ancestors().skip(1) {
+        let candidate = dir.join(EDITORCONFIG_FILENAME);
+        if !candidate.is_file() {
+            continue;
+        }
+        let editorconfig = EditorConfig::load(&candidate)?;
+        let root = editorconfig.is_root();
+        found.push(editorconfig);
+        if root {
+            break;
+        }
+    }
+
+    let found: Vec<&EditorConfig> = found.iter().collect();
+    apply_editorconfigs(&found, &file, config);
+    return Ok(());
+}
+
+/// Applies `found`, the `.editorconfig` files governing the absolute path
+/// `file` from the nearest one up, to `config`.
+pub fn apply_editorconfigs(found: &[&EditorConfig], file: &Path, config: &mut Config) {
+    for editorconfig in found.iter().rev() {
+        for (key, value) in editorconfig.properties(file) {
+            apply_property(config, &key, &value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::format::config::{Config, EndOfLine, IndentStyle};
+    use crate::format::editorconfig::{apply_editorconfig, EditorConfig};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn sections_match_by_glob() {
+        let editorconfig = EditorConfig::parse(
+            Path::new("/repo"),
+            "root = true\n\
+             \n\
+             [*]\n\
+             indent_size = 4\n\
+             \n\
+             [*.{html,htm}]\n\
+             indent_style = tab\n\
+             \n\
+             [/docs/*.html]\n\
+             end_of_line = CRLF\n",
+        );
+        assert!(editorconfig.is_root());
+
+        let props = editorconfig.properties(Path::new("/repo/a/b/index.html"));
+        assert_eq!(
+            props,
+            vec![
+                ("indent_size".to_string(), "4".to_string()),
+                ("indent_style".to_string(), "tab".to_string()),
+            ]
+        );
+
+        let props = editorconfig.properties(Path::new("/repo/docs/index.html"));
+        assert_eq!(props.last().unwrap().1, "crlf");
+
+        assert!(editorconfig
+            .properties(Path::new("/elsewhere/index.html"))
+            .is_empty());
+    }
+
+    #[test]
+    fn nearer_files_win_and_root_stops_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(repo.join("sub")).unwrap();
+        fs::write(
+            dir.path().join(".editorconfig"),
+            "[*]\ninsert_final_newline = false\n",
+        )
+        .unwrap();
+        fs::write(
+            repo.join(".editorconfig"),
+            "root = true\n[*]\nindent_style = tab\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        fs::write(
+            repo.join("sub/.editorconfig"),
+            "[*.html]\nend_of_line = lf\n",
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        apply_editorconfig(&repo.join("sub/index.html"), &mut config).unwrap();
+
+        assert_eq!(config.indent_style, IndentStyle::Tab);
+        assert_eq!(config.end_of_line, EndOfLine::Lf);
+        // root = true より上の .editorconfig は読まない
+        assert!(config.insert_final_newline);
+    }
+}
@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{path}: invalid config: {source}")]
+    Toml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
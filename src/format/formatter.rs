@@ -1,8 +1,88 @@
-use crate::format::config::Config;
-use crate::parse::kind::NodeKind;
-use crate::parse::node::Node;
-use std::fmt::format;
+use crate::format::config::{
+    AttributeQuotes, BooleanAttributes, CharRefStyle, Config, IndentStyle, OmittedEndTags,
+    VoidStyle,
+};
+use crate::parse::diagnostic::Diagnostic;
+use crate::parse::element::{is_raw_text, is_void};
+use crate::parse::err::ParseError;
+use crate::parse::node::{Attribute, Element, Node, Text, TextPart};
+use crate::parse::parser::Parser;
+use crate::tokenize::charref;
+use crate::tokenize::tokenizer::Tokenizer;
 
+/// Attributes whose presence alone means "on"; their value doesn't matter.
+const BOOLEAN_ATTRIBUTES: [&str; 24] = [
+    "allowfullscreen",
+    "async",
+    "autofocus",
+    "autoplay",
+    "checked",
+    "controls",
+    "default",
+    "defer",
+    "disabled",
+    "formnovalidate",
+    "inert",
+    "ismap",
+    "itemscope",
+    "loop",
+    "multiple",
+    "muted",
+    "nomodule",
+    "novalidate",
+    "open",
+    "playsinline",
+    "readonly",
+    "required",
+    "reversed",
+    "selected",
+];
+
+/// Whitespace as HTML counts it; unlike `char::is_whitespace` it leaves NBSP alone.
+const HTML_WHITESPACE: [char; 5] = [' ', '\t', '\n', '\r', '\x0C'];
+
+/// Whether a character can be written as itself in place of a reference
+/// without changing what the document means or hiding it from the reader.
+fn is_literal_safe(c: char) -> bool {
+    return !(matches!(c, '&' | '<' | '>' | '"' | '\'' | '\u{FFFD}')
+        || c.is_whitespace()
+        || c.is_control()
+        // 見えない文字
+        || matches!(c, '\u{AD}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}' | '\u{FEFF}'));
+}
+
+/// The text `s` stands for once its character references are decoded.
+fn decode(s: &str, in_attribute: bool) -> String {
+    let mut result: String = "".to_string();
+    let mut rest = s;
+    while let Some(at) = rest.find('&') {
+        result += &rest[..at];
+        rest = &rest[at..];
+        match charref::read(rest, in_attribute) {
+            Some(found) => {
+                result += &*found.decoded;
+                rest = &rest[found.len..];
+            }
+            None => {
+                result += "&";
+                rest = &rest[1..];
+            }
+        }
+    }
+    return result + rest;
+}
+
+/// What is left for `format_nodes` to write: a node, or the end tag of an
+/// element whose children come before it.
+enum Step<'a> {
+    Node(&'a Node, usize),
+    Line(String),
+}
+
+/// Formatters hold no per-call state, so one instance can be shared between
+/// threads and reused for any number of documents.
+#[derive(Debug, Clone)]
 pub struct Formatter {
     config: Config,
 }
@@ -12,117 +92,366 @@ impl Formatter {
         Formatter { config }
     }
 
-    fn str_parameter(&self, node: Box<Node>) -> String {
-        return format!(
-            "{}=\"{}\"",
-            node.lhs.unwrap().imm_s,
-            node.rhs.unwrap().imm_s
-        );
+    pub fn config(&self) -> &Config {
+        return &self.config;
     }
 
-    fn str_parameters(&self, node: Box<Node>) -> String {
-        if node.params.is_none() {
-            return "".to_string();
+    /// Writes the character reference `raw`, which stands for `decoded`, in
+    /// the configured style.
+    fn str_char_ref(&self, raw: &str, decoded: &str) -> String {
+        // 不正な数値参照などは書き換えない
+        if decoded.contains('\u{FFFD}') {
+            return raw.to_string();
         }
+        return match self.config.char_refs {
+            CharRefStyle::Keep => raw.to_string(),
+            CharRefStyle::Named => charref::name_of(decoded).unwrap_or(raw).to_string(),
+            CharRefStyle::Decimal => decoded
+                .chars()
+                .map(|c| format!("&#{};", c as u32))
+                .collect(),
+            CharRefStyle::Hex => decoded
+                .chars()
+                .map(|c| format!("&#x{:X};", c as u32))
+                .collect(),
+            CharRefStyle::Literal if decoded.chars().all(is_literal_safe) => decoded.to_string(),
+            CharRefStyle::Literal => raw.to_string(),
+        };
+    }
 
-        let mut s: String = "".to_string();
-        for node in node.params {
-            s += &*self.str_parameter(node);
+    fn str_text(&self, node: &Text) -> String {
+        if self.config.char_refs == CharRefStyle::Keep {
+            return node.text.clone();
         }
-
-        return s;
+        let mut text: String = "".to_string();
+        for part in &node.parts {
+            match part {
+                TextPart::CharRef { raw, decoded, .. } => text += &*self.str_char_ref(raw, decoded),
+                TextPart::Plain { text: plain, .. } => text += plain,
+            }
+        }
+        // &am&#112; を "&amp" にするなど、書き出した文字が前後とつながって別の
+        // 参照になるなら、書かれたままにしておく
+        if self.config.char_refs == CharRefStyle::Literal
+            && decode(&text, false) != decode(&node.text, false)
+        {
+            return node.text.clone();
+        }
+        return text;
     }
 
-    fn str_solo_tag(&self, node: Box<Node>) -> String {
-        let mut params: String = "".to_string();
+    /// Rewrites the character references in an attribute value.
+    fn str_value(&self, value: &str) -> String {
+        if self.config.char_refs == CharRefStyle::Keep {
+            return value.to_string();
+        }
+        let mut result: String = "".to_string();
+        let mut rest = value;
+        while let Some(at) = rest.find('&') {
+            result += &rest[..at];
+            rest = &rest[at..];
+            match charref::read(rest, true) {
+                Some(found) => {
+                    result += &*self.str_char_ref(&rest[..found.len], &found.decoded);
+                    rest = &rest[found.len..];
+                }
+                None => {
+                    result += "&";
+                    rest = &rest[1..];
+                }
+            }
+        }
+        let result = result + rest;
+        // 本文と同じく、新しい参照ができてしまうなら書き換えない
+        if self.config.char_refs == CharRefStyle::Literal
+            && decode(&result, true) != decode(value, true)
+        {
+            return value.to_string();
+        }
+        return result;
+    }
 
-        if node.params.is_some() {
-            params = self.str_parameters(node.params.unwrap())
+    /// Puts `value` in quotes, picking the other quote when the value
+    /// contains the configured one.
+    fn str_quoted(&self, value: &str) -> String {
+        let (quote, other, escaped) = match self.config.attribute_quotes {
+            AttributeQuotes::Double => ('"', '\'', "&quot;"),
+            AttributeQuotes::Single => ('\'', '"', "&#39;"),
+        };
+        if !value.contains(quote) {
+            return format!("{}{}{}", quote, value, quote);
         }
-        if params != "" {
-            params = " ".to_string() + &*params;
+        if !value.contains(other) {
+            return format!("{}{}{}", other, value, other);
         }
-        return format!("<{}{} />", node.imm_s, params);
+        return format!("{}{}{}", quote, value.replace(quote, escaped), quote);
     }
 
-    fn str_tag(&self, node: Box<Node>) -> String {
-        let mut params: String = "".to_string();
-        if node.params.is_some() {
-            for param in node.params {
-                params += &*self.str_parameter(param);
+    fn str_parameter(&self, attr: &Attribute) -> String {
+        let name = &attr.name;
+        let mut value: Option<String> = attr.value.as_ref().map(|v| self.str_value(v));
+
+        // disabled, disabled="", disabled="disabled" はどれも同じ意味
+        if BOOLEAN_ATTRIBUTES.contains(&name.to_ascii_lowercase().as_str()) {
+            let is_set = match &value {
+                Some(v) => v.is_empty() || v.eq_ignore_ascii_case(name),
+                None => true,
+            };
+            if is_set {
+                value = match self.config.boolean_attributes {
+                    BooleanAttributes::Bare => None,
+                    BooleanAttributes::Empty => Some("".to_string()),
+                    BooleanAttributes::Name => Some(name.to_string()),
+                };
             }
         }
-        if params != "" {
+
+        return match value {
+            Some(value) => format!("{}={}", name, self.str_quoted(&value)),
+            // 値のない属性
+            None => name.to_string(),
+        };
+    }
+
+    fn str_parameters(&self, attrs: &[Attribute]) -> String {
+        let mut params: Vec<String> = vec![];
+        for attr in attrs {
+            params.push(self.str_parameter(attr));
+        }
+
+        return params.join(" ");
+    }
+
+    fn str_solo_tag(&self, node: &Element) -> String {
+        let mut params = self.str_parameters(&node.attrs);
+        if !params.is_empty() {
+            params = " ".to_string() + &*params;
+        }
+        // <my-comp /> の / は意味があるので残す
+        if is_void(&node.name) && self.config.void_style == VoidStyle::Html {
+            return format!("<{}{}>", node.name, params);
+        }
+        return format!("<{}{} />", node.name, params);
+    }
+
+    fn str_tag(&self, node: &Element) -> String {
+        let mut params = self.str_parameters(&node.attrs);
+        if !params.is_empty() {
             params = " ".to_string() + &*params;
         }
 
-        return format!("<{}{}>", node.imm_s, params);
+        return format!("<{}{}>", node.name, params);
+    }
+
+    /// Writes a raw text element. Its contents are copied as they are; only
+    /// the whitespace around a script or style sheet is laid out.
+    fn format_raw_text(
+        &self,
+        node: &Element,
+        raw: &str,
+        close: &str,
+        deep: usize,
+        result: &mut String,
+    ) {
+        let ws: String = self.indent(deep);
+        let open = self.str_tag(node);
+        // 改行は後でまとめて end_of_line に変えるので、ここで "\n" に揃えておく
+        let content = raw.replace("\r\n", "\n").replace('\r', "\n");
+
+        // 終了タグがなければ中身は入力の最後まで続いているので、改行も足さずにそのまま置く。
+        // 足すと次に読んだときに中身が増える
+        if close.is_empty() {
+            *result += &*format!("{}{}{}", ws, open, content);
+            return;
+        }
+
+        // textarea や title などは空白も中身なので一切触らない
+        if !matches!(node.name.to_ascii_lowercase().as_str(), "script" | "style") {
+            *result += &*format!("{}{}{}{}\n", ws, open, content, close);
+            return;
+        }
+
+        let body = content.trim_matches(HTML_WHITESPACE);
+        if body.is_empty() {
+            *result += &*format!("{}{}{}\n", ws, open, close);
+        } else if !body.contains('\n') {
+            // 一行だけなら字下げした行に置く
+            *result += &*format!(
+                "{}{}\n{}{}\n{}{}\n",
+                ws,
+                open,
+                self.indent(deep + 1),
+                body,
+                ws,
+                close
+            );
+        } else {
+            // 複数行は文字列リテラルなどを壊さないよう、行頭の空白も含めてそのまま
+            let body = content.trim_end_matches(HTML_WHITESPACE);
+            *result += &*format!("{}{}{}\n{}{}\n", ws, open, body, ws, close);
+        }
+    }
+
+    fn indent(&self, deep: usize) -> String {
+        return match self.config.indent_style {
+            IndentStyle::Space => " ".repeat(self.config.ident * deep),
+            IndentStyle::Tab => "\t".repeat(deep),
+        };
     }
 
-    pub fn format(&self, nodes: Vec<Option<Box<Node>>>) -> String {
-        // ->->
-        let mut left_side: Vec<(String, bool)> = vec![];
-        // <-<-
-        let mut right_side: Vec<String> = vec![];
+    /// Writes `node`, leaving its children and end tag on `stack`.
+    fn format_element<'a>(
+        &self,
+        node: &'a Element,
+        deep: usize,
+        stack: &mut Vec<Step<'a>>,
+        result: &mut String,
+    ) {
+        let ws: String = self.indent(deep);
+        if node.is_solo() {
+            *result += &*format!("{}{}\n", ws, self.str_solo_tag(node));
+            return;
+        }
 
-        for node in nodes {
-            if node.as_ref().is_none() {
-                continue;
+        let open = self.str_tag(node);
+        // 省略されていた終了タグは、そのまま省略しておくこともできる
+        let keep_omitted = self.config.omitted_end_tags == OmittedEndTags::Keep;
+        // <plaintext> は閉じられないので、終了タグを足すとそれも中身になってしまう
+        let unclosable = node.name.eq_ignore_ascii_case("plaintext");
+        let close = if node.end_omitted && (keep_omitted || unclosable) {
+            "".to_string()
+        } else {
+            format!("</{}>", node.name)
+        };
+        match node.children.as_slice() {
+            [Node::RawText { text, .. }] if is_raw_text(&node.name) => {
+                self.format_raw_text(node, text, &close, deep, result);
+            }
+            [] => {
+                // 子要素がなければ一行にまとめる
+                *result += &*format!("{}{}{}\n", ws, open, close);
             }
+            children => {
+                *result += &*format!("{}{}\n", ws, open);
+                if !close.is_empty() {
+                    stack.push(Step::Line(format!("{}{}\n", ws, close)));
+                }
+                stack.extend(
+                    children
+                        .iter()
+                        .rev()
+                        .map(|child| Step::Node(child, deep + 1)),
+                );
+            }
+        }
+    }
 
-            match node.as_ref().unwrap().kind {
-                NodeKind::Text => {
-                    left_side.push((node.unwrap().imm_s, false));
+    pub(crate) fn format_nodes<'a>(
+        &self,
+        nodes: impl DoubleEndedIterator<Item = &'a Node>,
+        deep: usize,
+        result: &mut String,
+    ) {
+        // 深く入れ子になっていても再帰しないよう、残りはスタックに積んでおく
+        let mut stack: Vec<Step> = nodes.rev().map(|node| Step::Node(node, deep)).collect();
+        while let Some(step) = stack.pop() {
+            let (node, deep) = match step {
+                Step::Node(node, deep) => (node, deep),
+                Step::Line(line) => {
+                    *result += &*line;
+                    continue;
+                }
+            };
+            let ws: String = self.indent(deep);
+            match node {
+                Node::Element(element) => self.format_element(element, deep, &mut stack, result),
+                Node::Text(text) => {
+                    *result += &*format!("{}{}\n", ws, self.str_text(text));
+                }
+                Node::RawText { text, .. } => {
+                    *result += &*format!("{}{}\n", ws, text);
                 }
-                NodeKind::CommentTag => {
-                    left_side.push((format!("<!--{}-->", node.unwrap().imm_s), false));
+                // 読めなかった部分は元のまま写す。入力の最後まで続くものは後ろの空白も
+                // 含んでいるので、それは落とす
+                Node::Error { text, .. } => {
+                    *result += &*format!("{}{}\n", ws, text.trim_end_matches(HTML_WHITESPACE));
                 }
-                NodeKind::DoctypeTag => {
-                    left_side.push((format!("<!doctype {}>", node.unwrap().imm_s), false));
+                // <?php ?> などを <!-- --> に書き換えない。入力の終わりで切れたものは
+                // 後ろの空白まで含んでいるので、それは落とす
+                Node::Comment { raw: Some(raw), .. } => {
+                    *result += &*format!("{}{}\n", ws, raw.trim_end_matches(HTML_WHITESPACE));
                 }
-                NodeKind::SoloTag => left_side.push((self.str_solo_tag(node.unwrap()), false)),
-                NodeKind::Tag => {
-                    left_side.push((self.str_tag(node.clone().unwrap()), true));
-                    let mut children: String = "".to_string();
-                    if node.clone().unwrap().children.is_some() {
-                        children = self.format(node.clone().unwrap().children.unwrap());
-                    }
-                    if children != "" {
-                        left_side.push((children, false));
-                    }
-                    right_side.push(format!("</{}>", node.unwrap().imm_s));
+                Node::Comment { text, .. } => {
+                    *result += &*format!("{}<!--{}-->\n", ws, text);
+                }
+                Node::Doctype { text, .. } => {
+                    *result += &*format!("{}<!doctype {}>\n", ws, text);
                 }
-                _ => {}
             }
         }
+    }
 
+    pub fn format(&self, nodes: &[Node]) -> String {
         let mut result: String = "".to_string();
-        let ws: String = " ".repeat(self.config.ident);
+        self.format_nodes(nodes.iter(), 0, &mut result);
 
-        let mut deep: usize = 0;
-        for (left, need_dive) in left_side {
-            result += &*(ws.clone().to_string().repeat(deep) + left.as_str() + "\n");
-            if need_dive {
-                deep += 1;
-            }
-        }
-        for right in right_side {
-            result += &*(ws.clone().to_string().repeat(deep) + right.as_str() + "\n");
-            deep -= 1;
+        if !self.config.insert_final_newline && result.ends_with('\n') {
+            result.pop();
         }
+        return self.convert_newlines(result);
+    }
 
+    /// Lines are always built with "\n"; this switches them to the configured
+    /// line ending in one go.
+    pub(crate) fn convert_newlines(&self, result: String) -> String {
+        let eol = self.config.end_of_line.as_str();
+        if eol != "\n" {
+            return result.replace('\n', eol);
+        }
         return result;
     }
+
+    pub(crate) fn parse_str(&self, source: &str) -> Result<Vec<Node>, ParseError> {
+        let mut tokenizer = Tokenizer::new(source);
+        let tok = tokenizer.tokenize();
+
+        let mut parser = Parser::new();
+        return parser.parse(tok);
+    }
+
+    /// Runs the whole pipeline (tokenize -> parse -> format) over `source`.
+    pub fn format_str(&self, source: &str) -> Result<String, ParseError> {
+        return Ok(self.format(&self.parse_str(source)?));
+    }
+
+    /// Like `format_str`, but formats around parse errors instead of failing.
+    /// The parts that don't parse are copied from `source` byte for byte, and
+    /// every problem found is returned with the result.
+    pub fn format_str_recovering(&self, source: &str) -> (String, Vec<Diagnostic>) {
+        let mut tokenizer = Tokenizer::new(source);
+        let tok = tokenizer.tokenize();
+
+        let mut parser = Parser::new();
+        let recovered = parser.parse_recovering(tok);
+        return (self.format(&recovered.nodes), recovered.diagnostics);
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::format::config::Config;
+    use crate::format::config::{
+        AttributeQuotes, BooleanAttributes, CharRefStyle, Config, EndOfLine, IndentStyle,
+        OmittedEndTags, VoidStyle,
+    };
     use crate::format::formatter::Formatter;
-    use crate::parse::node::Node;
+    use crate::parse::node::{Attribute, Element, Node, Text};
     use crate::parse::parser::Parser;
     use crate::tokenize::tokenizer;
 
+    fn format_with(config: Config, html: &str) -> String {
+        return Formatter::new(config).format_str(html).unwrap();
+    }
+
     #[test]
     fn format_1() {
         let html = "<!DOCTYPE html> \
@@ -139,14 +468,524 @@ mod test {
         let mut parser_ = Parser::new();
         let nodes_res = parser_.parse(tok);
 
-        let mut nodes: Vec<Option<Box<Node>>> = vec![];
+        let mut nodes: Vec<Node> = vec![];
         match nodes_res {
-            Ok(n) => nodes = n.unwrap(),
+            Ok(n) => nodes = n,
             Err(err) => println!("{:#?}", err),
         }
 
         let formatter_ = Formatter::new(Config::default());
-        let s = formatter_.format(nodes.clone());
+        let s = formatter_.format(&nodes);
         println!("{}", s);
     }
+
+    #[test]
+    fn format_with_tabs_and_crlf() {
+        let config = Config {
+            indent_style: IndentStyle::Tab,
+            end_of_line: EndOfLine::Crlf,
+            insert_final_newline: false,
+            ..Config::default()
+        };
+        let formatter_ = Formatter::new(config);
+        assert_eq!(
+            formatter_.format_str("<ul><li>a</li></ul>").unwrap(),
+            "<ul>\r\n\t<li>\r\n\t\ta\r\n\t</li>\r\n</ul>"
+        );
+    }
+
+    #[test]
+    fn formatter_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Formatter>();
+        assert_send_sync::<Config>();
+    }
+
+    #[test]
+    fn format_nested() {
+        let html = "<!DOCTYPE html>\
+            <html>\
+            <body>\
+            <h1>hello</h1>\
+            <img src=\"https://google.com\"/>\
+            </body>\
+            </html>";
+
+        let formatter_ = Formatter::new(Config::default());
+        let s = formatter_.format_str(html).unwrap();
+        assert_eq!(
+            s,
+            "<!doctype html>\n\
+            <html>\n\
+            \x20 <body>\n\
+            \x20   <h1>\n\
+            \x20     hello\n\
+            \x20   </h1>\n\
+            \x20   <img src=\"https://google.com\" />\n\
+            \x20 </body>\n\
+            </html>\n"
+        );
+
+        // 整形済みのものを整形しても変わらない
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+    }
+
+    #[test]
+    fn format_text_with_symbols_and_attributes() {
+        let html = "<p title='say \"hi\"' hidden data-n=1>a - b, 1.2.3 x/y &amp; z</p>\
+                    <script>if (a<b) {}</script>";
+
+        let formatter_ = Formatter::new(Config::default());
+        let s = formatter_.format_str(html).unwrap();
+        assert_eq!(
+            s,
+            "<p title='say \"hi\"' hidden data-n=\"1\">\n\
+            \x20 a - b, 1.2.3 x/y &amp; z\n\
+            </p>\n\
+            <script>\n\
+            \x20 if (a<b) {}\n\
+            </script>\n"
+        );
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+    }
+
+    #[test]
+    fn format_char_refs_per_style() {
+        let html = "<p title=\"&#169; a&amp;b\">&nbsp;&#x3042;&lt;&AMP; &#0; &c</p>";
+        let format = |char_refs: CharRefStyle| {
+            let config = Config {
+                char_refs,
+                insert_final_newline: false,
+                ..Config::default()
+            };
+            let s = format_with(config, html);
+            return s.lines().nth(1).unwrap().trim().to_string()
+                + " | "
+                + s.lines().next().unwrap();
+        };
+
+        assert_eq!(
+            format(CharRefStyle::Keep),
+            "&nbsp;&#x3042;&lt;&AMP; &#0; &c | <p title=\"&#169; a&amp;b\">"
+        );
+        assert_eq!(
+            format(CharRefStyle::Named),
+            "&nbsp;&#x3042;&lt;&amp; &#0; &c | <p title=\"&copy; a&amp;b\">"
+        );
+        assert_eq!(
+            format(CharRefStyle::Decimal),
+            "&#160;&#12354;&#60;&#38; &#0; &c | <p title=\"&#169; a&#38;b\">"
+        );
+        assert_eq!(
+            format(CharRefStyle::Hex),
+            "&#xA0;&#x3042;&#x3C;&#x26; &#0; &c | <p title=\"&#xA9; a&#x26;b\">"
+        );
+        // 空白や記号は参照のまま残す
+        assert_eq!(
+            format(CharRefStyle::Literal),
+            "&nbsp;あ&lt;&AMP; &#0; &c | <p title=\"© a&amp;b\">"
+        );
+    }
+
+    #[test]
+    fn format_literal_char_refs_dont_make_new_refs() {
+        let formatter_ = Formatter::new(Config {
+            char_refs: CharRefStyle::Literal,
+            ..Config::default()
+        });
+        for text in ["&am&#112;;", "&a&#109;p;", "&lt&#x3b;"] {
+            let html = format!("<p title=\"{}\">{}</p>", text, text);
+            assert_eq!(
+                formatter_.format_str(&html).unwrap(),
+                format!("<p title=\"{}\">\n  {}\n</p>\n", text, text)
+            );
+        }
+        // & は参照のまま残るので、a だけ書き出しても "&amp;" と読めたまま
+        assert_eq!(
+            formatter_
+                .format_str("<p>&amp;&#97;mp;caf&eacute;</p>")
+                .unwrap(),
+            "<p>\n  &amp;amp;café\n</p>\n"
+        );
+    }
+
+    #[test]
+    fn format_unquoted_and_boolean_attributes() {
+        let html = "<td colspan=2 class=a><input disabled checked=\"\" selected=SELECTED \
+                    v-cloak value=\"\" title=it's /></td>";
+        let config = |attribute_quotes, boolean_attributes| Config {
+            attribute_quotes,
+            boolean_attributes,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            format_with(
+                config(AttributeQuotes::Double, BooleanAttributes::Bare),
+                html
+            ),
+            "<td colspan=\"2\" class=\"a\">\n\
+            \x20 <input disabled checked selected v-cloak value=\"\" title=\"it's\" />\n\
+            </td>\n"
+        );
+        assert_eq!(
+            format_with(
+                config(AttributeQuotes::Single, BooleanAttributes::Empty),
+                html
+            ),
+            "<td colspan='2' class='a'>\n\
+            \x20 <input disabled='' checked='' selected='' v-cloak value='' title=\"it's\" />\n\
+            </td>\n"
+        );
+        assert_eq!(
+            format_with(
+                config(AttributeQuotes::Double, BooleanAttributes::Name),
+                html
+            )
+            .lines()
+            .nth(1)
+            .unwrap(),
+            "  <input disabled=\"disabled\" checked=\"checked\" selected=\"selected\" \
+            v-cloak value=\"\" title=\"it's\" />"
+        );
+    }
+
+    #[test]
+    fn format_framework_attributes() {
+        let html = "<div :class=\"{ a: b }\" @click.stop=\"go(1)\" v-if=\"ok\" data-id=7>\
+                    <input [(ngModel)]=\"name\" #box *ngIf=\"show\" (keyup.enter)=\"add(box.value)\" \
+                    aria-label='Name' xlink:href=\"#i\" DISABLED /></div>";
+
+        let formatter_ = Formatter::new(Config::default());
+        let s = formatter_.format_str(html).unwrap();
+        assert_eq!(
+            s,
+            "<div :class=\"{ a: b }\" @click.stop=\"go(1)\" v-if=\"ok\" data-id=\"7\">\n\
+            \x20 <input [(ngModel)]=\"name\" #box *ngIf=\"show\" (keyup.enter)=\"add(box.value)\" \
+            aria-label=\"Name\" xlink:href=\"#i\" DISABLED />\n\
+            </div>\n"
+        );
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+    }
+
+    #[test]
+    fn format_keeps_tag_names_as_written() {
+        let html = "<MyComp :a=\"1\"><Sub-Item/></MyComp>\
+                    <DIV>a<BR>b</div><Script>let x = 1;</SCRIPT>";
+
+        let formatter_ = Formatter::new(Config::default());
+        let s = formatter_.format_str(html).unwrap();
+        // 終了タグは開始タグに合わせる
+        assert_eq!(
+            s,
+            "<MyComp :a=\"1\">\n\
+            \x20 <Sub-Item />\n\
+            </MyComp>\n\
+            <DIV>\n\
+            \x20 a\n\
+            \x20 <BR />\n\
+            \x20 b\n\
+            </DIV>\n\
+            <Script>\n\
+            \x20 let x = 1;\n\
+            </Script>\n"
+        );
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+    }
+
+    #[test]
+    fn format_void_elements_per_style() {
+        let html = "<head><meta charset=\"utf-8\"><link rel=icon href=a.png/></head>\
+                    <p>a<br>b<my-comp/></p>";
+        let html_style = format_with(
+            Config {
+                void_style: VoidStyle::Html,
+                ..Config::default()
+            },
+            html,
+        );
+        assert_eq!(
+            html_style,
+            "<head>\n\
+            \x20 <meta charset=\"utf-8\">\n\
+            \x20 <link rel=\"icon\" href=\"a.png/\">\n\
+            </head>\n\
+            <p>\n\
+            \x20 a\n\
+            \x20 <br>\n\
+            \x20 b\n\
+            \x20 <my-comp />\n\
+            </p>\n"
+        );
+        assert_eq!(
+            format_with(
+                Config {
+                    void_style: VoidStyle::Xhtml,
+                    ..Config::default()
+                },
+                html
+            ),
+            html_style
+                .replace("utf-8\">", "utf-8\" />")
+                .replace("a.png/\">", "a.png/\" />")
+                .replace("<br>", "<br />")
+        );
+    }
+
+    #[test]
+    fn format_omitted_end_tags() {
+        let html = "<ul><li>a<li>b</ul><p>c<p></p>";
+        let kept = format_with(
+            Config {
+                omitted_end_tags: OmittedEndTags::Keep,
+                ..Config::default()
+            },
+            html,
+        );
+        assert_eq!(
+            kept,
+            "<ul>\n\
+            \x20 <li>\n\
+            \x20   a\n\
+            \x20 <li>\n\
+            \x20   b\n\
+            </ul>\n\
+            <p>\n\
+            \x20 c\n\
+            <p></p>\n"
+        );
+        let formatter_ = Formatter::new(Config::default());
+        assert_eq!(formatter_.format_str(&kept).unwrap(), kept);
+
+        assert_eq!(
+            format_with(
+                Config {
+                    omitted_end_tags: OmittedEndTags::Insert,
+                    ..Config::default()
+                },
+                html
+            ),
+            "<ul>\n\
+            \x20 <li>\n\
+            \x20   a\n\
+            \x20 </li>\n\
+            \x20 <li>\n\
+            \x20   b\n\
+            \x20 </li>\n\
+            </ul>\n\
+            <p>\n\
+            \x20 c\n\
+            </p>\n\
+            <p></p>\n"
+        );
+    }
+
+    #[test]
+    fn format_raw_text_verbatim() {
+        let html = "<div><script>\n  const s = `a\n    b`;\n  if (x < 1) {}\n\n</script>\
+                    <style> p { color: red } </style>\
+                    <textarea>  a &lt; b\n  c </textarea><title>T &amp; U</title></div>";
+
+        let formatter_ = Formatter::new(Config {
+            char_refs: CharRefStyle::Literal,
+            ..Config::default()
+        });
+        let s = formatter_.format_str(html).unwrap();
+        assert_eq!(
+            s,
+            "<div>\n\
+            \x20 <script>\n\
+            \x20 const s = `a\n\
+            \x20   b`;\n\
+            \x20 if (x < 1) {}\n\
+            \x20 </script>\n\
+            \x20 <style>\n\
+            \x20   p { color: red }\n\
+            \x20 </style>\n\
+            \x20 <textarea>  a &lt; b\n\
+            \x20 c </textarea>\n\
+            \x20 <title>T &amp; U</title>\n\
+            </div>\n"
+        );
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+
+        let formatter_ = Formatter::new(Config {
+            end_of_line: EndOfLine::Crlf,
+            ..Config::default()
+        });
+        assert_eq!(
+            formatter_
+                .format_str("<pre><textarea>a\r\nb</textarea></pre>")
+                .unwrap(),
+            "<pre>\r\n  <textarea>a\r\nb</textarea>\r\n</pre>\r\n"
+        );
+    }
+
+    #[test]
+    fn format_keeps_bogus_comments() {
+        let html = "<?xml version=\"1.0\"?>\n<div><?php echo $x; ?><![CDATA[x]]>\
+                    </ x><!-- a --></<select></div>\n</\n";
+
+        let formatter_ = Formatter::new(Config::default());
+        let s = formatter_.format_str(html).unwrap();
+        assert_eq!(
+            s,
+            "<?xml version=\"1.0\"?>\n\
+            <div>\n\
+            \x20 <?php echo $x; ?>\n\
+            \x20 <![CDATA[x]]>\n\
+            \x20 </ x>\n\
+            \x20 <!-- a -->\n\
+            \x20 </<select>\n\
+            </div>\n\
+            </\n"
+        );
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+    }
+
+    #[test]
+    fn format_self_closing_raw_text_elements() {
+        let html = "<head>\n<script src=\"app.js\" />\n</head>\n<body><p>hi</p></body>";
+
+        let formatter_ = Formatter::new(Config::default());
+        let s = formatter_.format_str(html).unwrap();
+        // 後ろのタグは生テキストにならない
+        assert_eq!(
+            s,
+            "<head>\n\
+            \x20 <script src=\"app.js\" />\n\
+            </head>\n\
+            <body>\n\
+            \x20 <p>\n\
+            \x20   hi\n\
+            \x20 </p>\n\
+            </body>\n"
+        );
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+        assert!(formatter_
+            .format_str("<style/><p>a</p><script></script>")
+            .is_ok());
+    }
+
+    #[test]
+    fn format_around_parse_errors() {
+        let html = "<div><p>a</q >b</p>\n<ul><li>x</ul></div></SPAN\n><i>y";
+
+        let formatter_ = Formatter::new(Config::default());
+        assert!(formatter_.format_str(html).is_err());
+
+        let (s, diagnostics) = formatter_.format_str_recovering(html);
+        assert_eq!(
+            s,
+            "<div>\n\
+            \x20 <p>\n\
+            \x20   a\n\
+            \x20   </q >\n\
+            \x20   b\n\
+            \x20 </p>\n\
+            \x20 <ul>\n\
+            \x20   <li>\n\
+            \x20     x\n\
+            \x20 </ul>\n\
+            </div>\n\
+            </SPAN\n\
+            >\n\
+            <i>\n\
+            \x20 y\n"
+        );
+        assert_eq!(diagnostics.len(), 3);
+
+        let (again, _) = formatter_.format_str_recovering(&s);
+        assert_eq!(again, s);
+
+        // 書きかけのタグは属性として読み直さない
+        let (s, diagnostics) = formatter_.format_str_recovering("<div>\n<span\n</div>\n");
+        assert_eq!(s, "<div>\n  <span\n</div>\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(formatter_.format_str_recovering(&s).0, s);
+
+        // 閉じられていないコメントから後ろは書かれたまま
+        let html = "<div>\n<!-- start\n<p>a</p>\n</div>\n";
+        assert!(formatter_.format_str(html).is_err());
+        let (s, diagnostics) = formatter_.format_str_recovering(html);
+        assert_eq!(s, "<div>\n  <!-- start\n<p>a</p>\n</div>\n");
+        assert_eq!(
+            diagnostics[0].error.to_string(),
+            "comment was not closed".to_string()
+        );
+        assert_eq!(formatter_.format_str_recovering(&s).0, s);
+    }
+
+    #[test]
+    fn format_raw_text_cut_off_by_the_end() {
+        let formatter_ = Formatter::new(Config::default());
+        for html in [
+            "<div>\n<textarea>abc",
+            "<div>\n<title>a\n</div>\n",
+            "<script>\nlet a;\n",
+        ] {
+            let (once, _) = formatter_.format_str_recovering(html);
+            let (twice, _) = formatter_.format_str_recovering(&once);
+            assert_eq!(twice, once);
+        }
+        assert_eq!(
+            formatter_.format_str_recovering("<div>\n<textarea>abc\n").0,
+            "<div>\n  <textarea>abc\n"
+        );
+
+        // <plaintext> には終了タグを足さない
+        let formatter_ = Formatter::new(Config {
+            omitted_end_tags: OmittedEndTags::Insert,
+            ..Config::default()
+        });
+        let html = "<p>a</p><plaintext>x</p>\n";
+        let s = formatter_.format_str(html).unwrap();
+        assert_eq!(s, "<p>\n  a\n</p>\n<plaintext>x</p>\n");
+        assert_eq!(formatter_.format_str(&s).unwrap(), s);
+    }
+
+    #[test]
+    fn format_deep_nesting() {
+        // 字下げがあると出力が深さの二乗で増えるので、字下げなしで深さだけを見る
+        let depth = 100_000;
+        let html = "<div>".repeat(depth) + "x" + &"</div>".repeat(depth);
+        let formatter_ = Formatter::new(Config {
+            ident: 0,
+            ..Config::default()
+        });
+        let s = formatter_.format_str(&html).unwrap();
+        assert_eq!(
+            s,
+            "<div>\n".repeat(depth) + "x\n" + &"</div>\n".repeat(depth)
+        );
+        assert_eq!(
+            formatter_.format_range(&html, 0..1).unwrap(),
+            s.trim_end_matches('\n')
+        );
+    }
+
+    #[test]
+    fn format_built_tree() {
+        let mut p = Element::new("p");
+        p.attrs.push(Attribute::new("class", Some("note")));
+        p.children.push(Node::Text(Text::new("hello")));
+        let mut div = Element::new("div");
+        div.attrs.push(Attribute::new("hidden", None));
+        div.children.push(Node::Element(p));
+        div.children.push(Node::Element(Element::new("br")));
+
+        let formatter_ = Formatter::new(Config {
+            char_refs: CharRefStyle::Named,
+            ..Config::default()
+        });
+        assert_eq!(
+            formatter_.format(&[Node::Element(div)]),
+            "<div hidden>\n\
+            \x20 <p class=\"note\">\n\
+            \x20   hello\n\
+            \x20 </p>\n\
+            \x20 <br />\n\
+            </div>\n"
+        );
+    }
 }
@@ -0,0 +1,286 @@
+use crate::format::formatter::Formatter;
+use crate::parse::element::is_raw_text;
+use crate::parse::err::ParseError;
+use crate::parse::node::Node;
+use std::ops::Range;
+
+/// Nodes `first..=last` of one sibling list, `deep` levels below the root.
+struct Selection<'a> {
+    nodes: Vec<&'a Node>,
+    deep: usize,
+    first: usize,
+    last: usize,
+}
+
+fn start_of(node: &Node) -> usize {
+    return node.span().start.at_byte;
+}
+
+fn end_of(node: &Node) -> usize {
+    return node.span().end.at_byte;
+}
+
+fn overlaps(node: &Node, range: &Range<usize>) -> bool {
+    if range.is_empty() {
+        return start_of(node) <= range.start && range.start < end_of(node);
+    }
+    return start_of(node) < range.end && range.start < end_of(node);
+}
+
+/// Byte range of the lines `first..=last` (1-based) of `source`, without the
+/// final line break.
+pub fn line_range(source: &str, first: usize, last: usize) -> Range<usize> {
+    let mut starts: Vec<usize> = vec![0];
+    starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+
+    let line_start = |line: usize| -> usize {
+        return *starts.get(line.max(1) - 1).unwrap_or(&source.len());
+    };
+    let mut end = line_start(last + 1);
+    if end > 0 && source[..end].ends_with('\n') && last < starts.len() {
+        end -= 1;
+    }
+    return line_start(first)..end.max(line_start(first));
+}
+
+/// Every level of the tree the range reaches into, from the root down to the
+/// innermost sibling list that holds the whole range.
+fn select<'a>(nodes: &'a [Node], range: &Range<usize>) -> Vec<Selection<'a>> {
+    let mut path: Vec<Selection<'a>> = vec![];
+    let mut nodes: Vec<&'a Node> = nodes.iter().collect();
+    let mut deep: usize = 0;
+
+    loop {
+        let hits: Vec<usize> = (0..nodes.len())
+            .filter(|i| overlaps(nodes[*i], range))
+            .collect();
+        let (first, last) = match (hits.first(), hits.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return path,
+        };
+
+        let node = nodes[first];
+        path.push(Selection {
+            nodes: nodes.clone(),
+            deep,
+            first,
+            last,
+        });
+        // <script> などの中身は一つのかたまりとして扱う
+        let children: Vec<&'a Node> = match node {
+            Node::Element(element) if first == last && !is_raw_text(&element.name) => {
+                element.children.iter().collect()
+            }
+            _ => return path,
+        };
+
+        // 範囲が子要素の中に収まっているときだけ潜る
+        let inside = match (children.first(), children.last()) {
+            (Some(head), Some(tail)) => start_of(head) <= range.start && range.end <= end_of(tail),
+            _ => false,
+        };
+        if !inside {
+            return path;
+        }
+        nodes = children;
+        deep += 1;
+    }
+}
+
+fn line_start(source: &str, at: usize) -> usize {
+    return source[..at].rfind('\n').map_or(0, |i| i + 1);
+}
+
+fn line_end(source: &str, at: usize) -> usize {
+    let end = source[at..].find('\n').map_or(source.len(), |i| at + i);
+    if end > at && source[..end].ends_with('\r') {
+        return end - 1;
+    }
+    return end;
+}
+
+impl Formatter {
+    /// Reformats only the nodes overlapping the byte `range` of `source` and
+    /// returns the whole document with everything else left as it was.
+    ///
+    /// The range is widened to whole elements, and further to whole lines:
+    /// siblings sharing a line with the selection are pulled in, and when an
+    /// enclosing tag sits on the same line the enclosing element is
+    /// reformatted instead. The result therefore always parses like the input.
+    pub fn format_range(&self, source: &str, range: Range<usize>) -> Result<String, ParseError> {
+        let nodes = self.parse_str(source)?;
+        let range = range.start.min(source.len())..range.end.min(source.len());
+
+        let mut path = select(&nodes, &range);
+        while let Some(selection) = path.pop() {
+            let mut first = selection.first;
+            let mut last = selection.last;
+
+            let mut start = start_of(selection.nodes[first]);
+            while first > 0 && end_of(selection.nodes[first - 1]) > line_start(source, start) {
+                first -= 1;
+                start = start_of(selection.nodes[first]);
+            }
+            let mut end = end_of(selection.nodes[last]);
+            while last + 1 < selection.nodes.len()
+                && start_of(selection.nodes[last + 1]) < line_end(source, end)
+            {
+                last += 1;
+                end = end_of(selection.nodes[last]);
+            }
+
+            let from = line_start(source, start);
+            let to = line_end(source, end);
+            let alone = source[from..start].trim().is_empty() && source[end..to].trim().is_empty();
+            if !alone && !path.is_empty() {
+                // 親のタグが同じ行にあるので、親ごと整形する
+                continue;
+            }
+
+            let selected = selection.nodes[first..=last].iter().copied();
+            let mut fragment: String = "".to_string();
+            self.format_nodes(selected, selection.deep, &mut fragment);
+            if fragment.ends_with('\n') {
+                fragment.pop();
+            }
+            let fragment = self.convert_newlines(fragment);
+
+            return Ok(source[..from].to_string() + &fragment + &source[to..]);
+        }
+
+        return Ok(source.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::format::config::Config;
+    use crate::format::formatter::Formatter;
+    use crate::format::range::line_range;
+
+    const HTML: &str = "<html>\n\
+                        <body>\n\
+                        <h1>   hello</h1>\n\
+                        <p>a</p><p>b</p>\n\
+                        <ul><li>c</li></ul>\n\
+                        </body>\n\
+                        </html>\n";
+
+    #[test]
+    fn line_range_covers_whole_lines() {
+        let source = "a\nbc\nd";
+        assert_eq!(&source[line_range(source, 2, 2)], "bc");
+        assert_eq!(&source[line_range(source, 2, 3)], "bc\nd");
+        assert_eq!(&source[line_range(source, 1, 9)], "a\nbc\nd");
+    }
+
+    #[test]
+    fn format_range_only_touches_selected_element() {
+        let formatter_ = Formatter::new(Config::default());
+        let range = line_range(HTML, 3, 3);
+        assert_eq!(
+            formatter_.format_range(HTML, range).unwrap(),
+            "<html>\n\
+             <body>\n\
+             \x20   <h1>\n\
+             \x20     hello\n\
+             \x20   </h1>\n\
+             <p>a</p><p>b</p>\n\
+             <ul><li>c</li></ul>\n\
+             </body>\n\
+             </html>\n"
+        );
+    }
+
+    #[test]
+    fn format_range_pulls_in_siblings_on_the_same_line() {
+        let formatter_ = Formatter::new(Config::default());
+        let start = HTML.find("<p>b").unwrap();
+        assert_eq!(
+            formatter_.format_range(HTML, start..start + 1).unwrap(),
+            "<html>\n\
+             <body>\n\
+             <h1>   hello</h1>\n\
+             \x20   <p>\n\
+             \x20     a\n\
+             \x20   </p>\n\
+             \x20   <p>\n\
+             \x20     b\n\
+             \x20   </p>\n\
+             <ul><li>c</li></ul>\n\
+             </body>\n\
+             </html>\n"
+        );
+    }
+
+    #[test]
+    fn format_range_widens_to_parent_on_the_same_line() {
+        let formatter_ = Formatter::new(Config::default());
+        let start = HTML.find("<li>").unwrap();
+        let formatted = formatter_.format_range(HTML, start..start + 4).unwrap();
+        assert!(formatted.contains("\n    <ul>\n      <li>\n        c\n      </li>\n    </ul>\n"));
+        assert!(formatted.starts_with("<html>\n<body>\n<h1>   hello</h1>\n"));
+    }
+
+    #[test]
+    fn format_range_outside_nodes_changes_nothing() {
+        let formatter_ = Formatter::new(Config::default());
+        let source = "<p>a</p>\n\n<p>b</p>\n";
+        assert_eq!(formatter_.format_range(source, 9..9).unwrap(), source);
+    }
+
+    #[test]
+    fn format_range_after_multibyte_text() {
+        let formatter_ = Formatter::new(Config::default());
+        let source = "<div>\n<p>日本語😀</p>\n<ul><li>c</li></ul>\n</div>\n";
+        let range = line_range(source, 3, 3);
+        assert_eq!(
+            formatter_.format_range(source, range).unwrap(),
+            "<div>\n\
+             <p>日本語😀</p>\n\
+             \x20 <ul>\n\
+             \x20   <li>\n\
+             \x20     c\n\
+             \x20   </li>\n\
+             \x20 </ul>\n\
+             </div>\n"
+        );
+    }
+
+    #[test]
+    fn format_range_takes_byte_offsets() {
+        let formatter_ = Formatter::new(Config::default());
+        let source = "<p>日本語</p><p>b</p>\n<ul><li>c</li></ul>\n";
+        let expected = "<p>日本語</p><p>b</p>\n<ul>\n  <li>\n    c\n  </li>\n</ul>\n";
+
+        // 文字数ではなくバイト数で数える
+        let at = source.find("<ul>").unwrap();
+        assert_eq!(
+            formatter_.format_range(source, at..at + 1).unwrap(),
+            expected
+        );
+
+        // 文字の途中を指されても落ちない
+        let inside = source.find('本').unwrap() + 1;
+        assert_eq!(
+            formatter_.format_range(source, inside..inside).unwrap(),
+            "<p>\n  日本語\n</p>\n<p>\n  b\n</p>\n<ul><li>c</li></ul>\n"
+        );
+    }
+
+    #[test]
+    fn format_range_keeps_raw_text_whole() {
+        let formatter_ = Formatter::new(Config::default());
+        let source = "<div>\n<script>\n  let a;\n    let b;\n</script>\n</div>\n";
+        let range = line_range(source, 4, 4);
+        assert_eq!(
+            formatter_.format_range(source, range).unwrap(),
+            "<div>\n\
+             \x20 <script>\n\
+             \x20 let a;\n\
+             \x20   let b;\n\
+             \x20 </script>\n\
+             </div>\n"
+        );
+    }
+}
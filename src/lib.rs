@@ -1,5 +1,6 @@
+#![allow(clippy::needless_return)]
 extern crate core;
 
-pub(crate) mod format;
-pub(crate) mod parse;
-pub(crate) mod tokenize;
+pub mod format;
+pub mod parse;
+pub mod tokenize;
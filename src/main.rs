@@ -1,3 +1,15 @@
-fn main() {
-    println!("Hello, world!");
+#![allow(clippy::needless_return)]
+
+mod cli;
+
+use clap::Parser;
+use cli::args::{Args, Command};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    return match args.command {
+        Some(Command::Lsp) => cli::lsp::run(&args),
+        None => cli::runner::run(&args),
+    };
 }
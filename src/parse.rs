@@ -1,4 +1,6 @@
-mod err;
-pub mod kind;
+pub mod diagnostic;
+pub mod document;
+pub mod element;
+pub mod err;
 pub mod node;
 pub mod parser;
@@ -0,0 +1,16 @@
+use crate::parse::err::ParseError;
+use crate::tokenize::position::Span;
+
+/// A problem the recovering parser found and stepped over.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub error: ParseError,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(error: ParseError) -> Diagnostic {
+        let span = error.span().cloned().unwrap_or_default();
+        return Diagnostic { error, span };
+    }
+}
@@ -0,0 +1,291 @@
+use crate::parse::node::Node;
+use std::sync::Arc;
+
+/// Refers to one node of a `Document`. Ids are plain indices, so they are
+/// cheap to copy and keep around; they are only meaningful for the document
+/// that handed them out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+struct Slot {
+    node: Node,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    next_sibling: Option<NodeId>,
+}
+
+/// A document tree kept in one flat list, so that any node can be asked for
+/// its parent and siblings as well as its children.
+///
+/// Elements are stored without their `children`; those are the ids
+/// `Document::children` returns.
+///
+/// Clones share their nodes until one of them is changed, so a document can
+/// be cloned and handed to other threads cheaply.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    // 親は必ず子より前にある
+    slots: Arc<Vec<Slot>>,
+    roots: Arc<Vec<NodeId>>,
+}
+
+impl Document {
+    pub fn new() -> Document {
+        return Document::default();
+    }
+
+    /// Adds `node` as the last child of `parent`, or as the last top-level
+    /// node. An element's children are added along with it.
+    pub fn append(&mut self, parent: Option<NodeId>, node: Node) -> NodeId {
+        let id = NodeId(self.slots.len());
+        // 深い木でも再帰しないよう、まだ足していないノードはスタックに積む
+        let mut stack: Vec<(Option<NodeId>, Node)> = vec![(parent, node)];
+        while let Some((parent, mut node)) = stack.pop() {
+            let children = match &mut node {
+                Node::Element(element) => std::mem::take(&mut element.children),
+                _ => vec![],
+            };
+            let added = self.append_one(parent, node);
+            stack.extend(children.into_iter().rev().map(|child| (Some(added), child)));
+        }
+        return id;
+    }
+
+    fn append_one(&mut self, parent: Option<NodeId>, node: Node) -> NodeId {
+        let slots = Arc::make_mut(&mut self.slots);
+        let id = NodeId(slots.len());
+        slots.push(Slot {
+            node,
+            parent,
+            children: vec![],
+            next_sibling: None,
+        });
+        let siblings = match parent {
+            Some(parent) => &mut slots[parent.0].children,
+            None => Arc::make_mut(&mut self.roots),
+        };
+        let last = siblings.last().copied();
+        siblings.push(id);
+        if let Some(last) = last {
+            slots[last.0].next_sibling = Some(id);
+        }
+        return id;
+    }
+
+    /// The top-level nodes, in document order.
+    pub fn roots(&self) -> &[NodeId] {
+        return &self.roots;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.slots.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.slots.is_empty();
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        return &self.slots[id.0].node;
+    }
+
+    /// The node `id`, to change in place. An element's `children` are not
+    /// part of the document, so changes to them are lost; use `append` to
+    /// add children.
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        return &mut Arc::make_mut(&mut self.slots)[id.0].node;
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        return self.slots[id.0].parent;
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        return &self.slots[id.0].children;
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        return self.slots[id.0].next_sibling;
+    }
+
+    /// The parent, its parent and so on up to a top-level node.
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_> {
+        return Ancestors {
+            document: self,
+            next: self.parent(id),
+        };
+    }
+
+    /// Every node below `id`, in document order.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        return Descendants {
+            document: self,
+            stack: self.children(id).iter().rev().copied().collect(),
+        };
+    }
+
+    /// The tree under `id` as an owned node, children included.
+    pub fn to_node(&self, id: NodeId) -> Node {
+        let order: Vec<NodeId> = std::iter::once(id).chain(self.descendants(id)).collect();
+
+        // 後ろから組み立てると、子は先頭の子を一番上にして built に積まれている
+        let mut built: Vec<Node> = vec![];
+        for id in order.into_iter().rev() {
+            let mut node = self.node(id).clone();
+            if let Node::Element(element) = &mut node {
+                let from = built.len() - self.children(id).len();
+                element.children = built.drain(from..).rev().collect();
+            }
+            built.push(node);
+        }
+        return built.pop().unwrap();
+    }
+
+    /// The whole document as owned nodes.
+    pub fn to_nodes(&self) -> Vec<Node> {
+        return self.roots.iter().map(|id| self.to_node(*id)).collect();
+    }
+
+    /// Like `to_nodes`, but moves the nodes out instead of copying them when
+    /// no clone of the document shares them.
+    pub fn into_nodes(self) -> Vec<Node> {
+        let slots = Arc::try_unwrap(self.slots).unwrap_or_else(|shared| (*shared).clone());
+        let mut built: Vec<Option<Node>> = Vec::with_capacity(slots.len());
+        built.resize_with(slots.len(), || None);
+
+        // 子は親より後ろにあるので、後ろから組み立てれば子は先にできている
+        for (i, slot) in slots.into_iter().enumerate().rev() {
+            let mut node = slot.node;
+            if let Node::Element(element) = &mut node {
+                element.children = slot
+                    .children
+                    .iter()
+                    .map(|child| built[child.0].take().unwrap())
+                    .collect();
+            }
+            built[i] = Some(node);
+        }
+        return self
+            .roots
+            .iter()
+            .map(|id| built[id.0].take().unwrap())
+            .collect();
+    }
+}
+
+impl From<Vec<Node>> for Document {
+    fn from(nodes: Vec<Node>) -> Document {
+        let mut document = Document::new();
+        for node in nodes {
+            document.append(None, node);
+        }
+        return document;
+    }
+}
+
+pub struct Ancestors<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        self.next = self.document.parent(id);
+        return Some(id);
+    }
+}
+
+pub struct Descendants<'a> {
+    document: &'a Document,
+    // 次に返すものが最後に来る
+    stack: Vec<NodeId>,
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.stack.pop()?;
+        self.stack
+            .extend(self.document.children(id).iter().rev().copied());
+        return Some(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse::document::{Document, NodeId};
+    use crate::parse::node::{Element, Node, Text};
+
+    fn name(document: &Document, id: NodeId) -> String {
+        return match document.node(id) {
+            Node::Element(element) => element.name.clone(),
+            Node::Text(text) => text.text.clone(),
+            other => format!("{:?}", other),
+        };
+    }
+
+    #[test]
+    fn navigates_the_tree() {
+        let mut ul = Element::new("ul");
+        for item in ["a", "b"] {
+            let mut li = Element::new("li");
+            li.children.push(Node::Text(Text::new(item)));
+            ul.children.push(Node::Element(li));
+        }
+        let document = Document::from(vec![Node::Element(ul), Node::Element(Element::new("p"))]);
+
+        let roots = document.roots();
+        assert_eq!(roots.len(), 2);
+        assert_eq!(document.next_sibling(roots[0]), Some(roots[1]));
+        assert_eq!(document.next_sibling(roots[1]), None);
+
+        let names: Vec<String> = document
+            .descendants(roots[0])
+            .map(|id| name(&document, id))
+            .collect();
+        assert_eq!(names, vec!["li", "a", "li", "b"]);
+
+        let b = document.descendants(roots[0]).last().unwrap();
+        let up: Vec<String> = document
+            .ancestors(b)
+            .map(|id| name(&document, id))
+            .collect();
+        assert_eq!(up, vec!["li", "ul"]);
+        assert_eq!(document.parent(roots[0]), None);
+
+        // 子は node() には入らず、children() で引く
+        match document.node(roots[0]) {
+            Node::Element(ul) => assert!(ul.children.is_empty()),
+            other => panic!("expected an element, got {:?}", other),
+        }
+        assert_eq!(document.children(roots[0]).len(), 2);
+    }
+
+    #[test]
+    fn clones_share_nodes_until_changed() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Document>();
+
+        let mut ul = Element::new("ul");
+        ul.children.push(Node::Element(Element::new("li")));
+        let nodes = vec![Node::Element(ul), Node::Text(Text::new("a"))];
+        let document = Document::from(nodes.clone());
+
+        let mut copy = document.clone();
+        let li = copy.children(copy.roots()[0])[0];
+        copy.append(Some(li), Node::Text(Text::new("b")));
+        if let Node::Text(text) = copy.node_mut(copy.roots()[1]) {
+            text.text = "c".to_string();
+        }
+
+        assert_eq!(copy.len(), 4);
+        assert_eq!(document.len(), 3);
+        assert_eq!(document.clone().into_nodes(), nodes);
+        assert_eq!(document.into_nodes(), nodes);
+    }
+}
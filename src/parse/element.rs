@@ -0,0 +1,200 @@
+/// Elements that never have contents or an end tag: `<br>`, `<img>`, ...
+pub const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Whether `name` is one of `names`. Tag names are kept as written, so
+/// they are compared ignoring ASCII case.
+fn is_one_of(names: &[&str], name: &str) -> bool {
+    return names.iter().any(|n| n.eq_ignore_ascii_case(name));
+}
+
+pub fn is_void(name: &str) -> bool {
+    return is_one_of(&VOID_ELEMENTS, name);
+}
+
+/// Elements whose end tag may be left out: it is implied by the next sibling
+/// or by the end of the parent.
+pub const OPTIONAL_END_TAGS: [&str; 19] = [
+    "html", "head", "body", "p", "li", "dt", "dd", "rt", "rp", "optgroup", "option", "colgroup",
+    "caption", "thead", "tbody", "tfoot", "tr", "td", "th",
+];
+
+/// Start tags that end an open `<p>`.
+const CLOSES_P: [&str; 38] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dd",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "search",
+    "section",
+    "table",
+    "ul",
+];
+
+pub fn has_optional_end_tag(name: &str) -> bool {
+    return is_one_of(&OPTIONAL_END_TAGS, name);
+}
+
+/// Whether the start tag `next` ends the open element `open` without an end
+/// tag, like a `<li>` ends the `<li>` before it.
+pub fn is_closed_by(open: &str, next: &str) -> bool {
+    let next = next.to_ascii_lowercase();
+    let next = next.as_str();
+    return match open.to_ascii_lowercase().as_str() {
+        "p" => CLOSES_P.contains(&next),
+        "li" => next == "li",
+        "dt" | "dd" => matches!(next, "dt" | "dd"),
+        "rt" | "rp" => matches!(next, "rt" | "rp"),
+        "optgroup" => next == "optgroup",
+        "option" => matches!(next, "option" | "optgroup"),
+        "head" => next == "body",
+        "thead" | "tbody" => matches!(next, "tbody" | "tfoot"),
+        "tfoot" => next == "tbody",
+        "tr" => matches!(next, "tr" | "thead" | "tbody" | "tfoot"),
+        "td" | "th" => matches!(next, "td" | "th" | "tr" | "thead" | "tbody" | "tfoot"),
+        _ => false,
+    };
+}
+
+/// Elements that the spec counts as special. A `<li>`, `<dd>` or `<dt>`
+/// inside one of these, other than `address`, `div` and `p`, doesn't end a
+/// list item outside it.
+const SPECIAL: [&str; 57] = [
+    "address",
+    "applet",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "button",
+    "caption",
+    "center",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "html",
+    "li",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "nav",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "pre",
+    "search",
+    "section",
+    "select",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Elements that bound the button scope. A block start tag or a `</p>`
+/// inside one of these doesn't end a `<p>` outside it.
+const BUTTON_SCOPE: [&str; 10] = [
+    "applet", "button", "caption", "html", "marquee", "object", "table", "td", "th", "template",
+];
+
+pub fn is_button_scope(name: &str) -> bool {
+    return is_one_of(&BUTTON_SCOPE, name);
+}
+
+/// Whether the open element `open` keeps the start tag `next` from ending
+/// elements it is inside of. A `<li>` ends the `li` around a `<b>`, but not
+/// the `li` of an outer list.
+pub fn stops_implied_end(open: &str, next: &str) -> bool {
+    let open = open.to_ascii_lowercase();
+    let open = open.as_str();
+    let next = next.to_ascii_lowercase();
+    let next = next.as_str();
+    return match next {
+        "li" | "dd" | "dt" => SPECIAL.contains(&open) && !matches!(open, "address" | "div" | "p"),
+        "td" | "th" | "tr" | "thead" | "tbody" | "tfoot" => {
+            matches!(open, "html" | "table" | "template")
+        }
+        _ if CLOSES_P.contains(&next) => BUTTON_SCOPE.contains(&open),
+        // ほかは一番内側の要素しか閉じない
+        _ => true,
+    };
+}
+
+/// Elements whose contents are not markup but one piece of text that runs up
+/// to their end tag: scripts, style sheets and a few legacy elements.
+pub const RAW_TEXT_ELEMENTS: [&str; 7] = [
+    "script",
+    "style",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Like raw text elements, but character references are read in them.
+pub const ESCAPABLE_RAW_TEXT_ELEMENTS: [&str; 2] = ["textarea", "title"];
+
+pub fn is_raw_text(name: &str) -> bool {
+    return is_one_of(&RAW_TEXT_ELEMENTS, name) || is_one_of(&ESCAPABLE_RAW_TEXT_ELEMENTS, name);
+}
@@ -1,15 +1,38 @@
 use crate::tokenize::kind::TokenKind;
+use crate::tokenize::position::Span;
 use crate::tokenize::token::Token;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum ParseError {
-    #[error("unexpected token (expect: {expected:?}, found {found:?})")]
-    UnexpectedToken { expected: TokenKind, found: Token },
-    #[error("unexpected text (expected: {expected:?}, found: {found:?})")]
-    UnexpectedText { expected: String, found: String },
+    #[error("unexpected token (expect: {expected:?}, found {:?})", .found.kind)]
+    UnexpectedToken {
+        expected: TokenKind,
+        found: Box<Token>,
+    },
     #[error("opening tag & closing tag name was miss matched (open: {open:?}, close: {close:?})")]
-    TagMissMatch { open: String, close: String },
+    TagMissMatch {
+        open: String,
+        close: String,
+        span: Span,
+    },
+    #[error("comment was not closed")]
+    UnclosedComment { span: Span },
+    #[error("tag {name:?} was not closed")]
+    UnclosedTag { name: String, span: Span },
     #[error("unknown parse error")]
     Unknown,
 }
+
+impl ParseError {
+    /// Where in the source the offending text is.
+    pub fn span(&self) -> Option<&Span> {
+        return match self {
+            ParseError::UnexpectedToken { found, .. } => Some(&found.span),
+            ParseError::TagMissMatch { span, .. }
+            | ParseError::UnclosedComment { span }
+            | ParseError::UnclosedTag { span, .. } => Some(span),
+            ParseError::Unknown => None,
+        };
+    }
+}
@@ -1,34 +1,129 @@
-use crate::parse::kind::NodeKind;
+use crate::parse::element::is_void;
+use crate::tokenize::position::Span;
+pub use crate::tokenize::token::Attribute;
 
-#[derive(Debug, Clone)]
-pub struct Node {
-    pub kind: NodeKind,
-    pub imm_s: String,
-    pub lhs: Option<Box<Node>>,
-    pub rhs: Option<Box<Node>>,
-    pub children: Option<Vec<Option<Box<Node>>>>,
-    pub params: Option<Box<Node>>,
-    // pub imm_f: f64,
-    // pub imm_i: i64,
+/// One node of the document tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(Text),
+    /// The contents of a raw text element such as `<script>`, as written.
+    RawText {
+        text: String,
+        span: Span,
+    },
+    /// `<!--text-->`
+    Comment {
+        text: String,
+        /// The source, when the comment wasn't written as `<!--text-->`:
+        /// `<?php ... ?>`, `<![CDATA[...]]>`, `</ x>` and the like. It is
+        /// copied back as it is.
+        raw: Option<String>,
+        span: Span,
+    },
+    /// `<!doctype text>`
+    Doctype {
+        text: String,
+        span: Span,
+    },
+    /// Source the parser couldn't make sense of, like an end tag that closes
+    /// nothing. Only the recovering parser produces these.
+    Error {
+        text: String,
+        span: Span,
+    },
 }
 
 impl Node {
-    pub fn new(
-        kind: NodeKind,
-        lhs: Option<Box<Node>>,
-        rhs: Option<Box<Node>>,
-        children: Option<Vec<Option<Box<Node>>>>,
-        params: Option<Box<Node>>,
-        s: String,
-    ) -> Node {
-        return Node {
-            kind,
-            lhs,
-            rhs,
-            children,
-            params,
-
-            imm_s: s,
+    pub fn span(&self) -> &Span {
+        return match self {
+            Node::Element(element) => &element.span,
+            Node::Text(text) => &text.span,
+            Node::RawText { span, .. }
+            | Node::Comment { span, .. }
+            | Node::Doctype { span, .. }
+            | Node::Error { span, .. } => span,
         };
     }
 }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<Attribute>,
+    pub children: Vec<Node>,
+    pub span: Span,
+    // <x /> と書かれていた
+    pub self_closing: bool,
+    // 終了タグが省略されていた: <li>a<li>b
+    pub end_omitted: bool,
+}
+
+impl Element {
+    pub fn new(name: &str) -> Element {
+        return Element {
+            name: name.to_string(),
+            attrs: vec![],
+            children: vec![],
+            span: Span::default(),
+            self_closing: false,
+            end_omitted: false,
+        };
+    }
+
+    /// Whether the element is a single tag with no contents and no end tag:
+    /// `<x />`, or a void element like `<br>`.
+    pub fn is_solo(&self) -> bool {
+        return self.self_closing || is_void(&self.name);
+    }
+}
+
+impl Drop for Element {
+    fn drop(&mut self) {
+        // 子を一つずつ外してから捨てれば、深く入れ子になっていても再帰しない
+        let mut rest = std::mem::take(&mut self.children);
+        while let Some(mut node) = rest.pop() {
+            if let Node::Element(element) = &mut node {
+                rest.append(&mut element.children);
+            }
+        }
+    }
+}
+
+/// A run of text, without the whitespace around it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    /// As written, character references included.
+    pub text: String,
+    /// The same text, split where character references are.
+    pub parts: Vec<TextPart>,
+    pub span: Span,
+}
+
+impl Text {
+    /// Text with no character references in it.
+    pub fn new(text: &str) -> Text {
+        return Text {
+            text: text.to_string(),
+            parts: vec![TextPart::Plain {
+                text: text.to_string(),
+                span: Span::default(),
+            }],
+            span: Span::default(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextPart {
+    Plain {
+        text: String,
+        span: Span,
+    },
+    /// `&amp;`: `raw` as written, `decoded` the text it stands for.
+    CharRef {
+        raw: String,
+        decoded: String,
+        span: Span,
+    },
+}
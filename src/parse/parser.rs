@@ -1,371 +1,557 @@
+use crate::parse::diagnostic::Diagnostic;
+use crate::parse::document::{Document, NodeId};
+use crate::parse::element::{
+    has_optional_end_tag, is_button_scope, is_closed_by, is_raw_text, stops_implied_end,
+};
 use crate::parse::err::ParseError;
-use crate::parse::kind::NodeKind;
-use crate::parse::kind::NodeKind::{Identifier, SoloTag, Tag, VString};
-use crate::parse::node::Node;
+use crate::parse::node::{Element, Node, Text, TextPart};
 use crate::tokenize::kind::TokenKind;
+use crate::tokenize::position::{Position, Span};
 use crate::tokenize::token::Token;
+use std::collections::HashMap;
+
+fn is_white(c: char) -> bool {
+    return matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ');
+}
+
+/// Span of the bytes `from..to` of the token's text.
+fn sub_span(tok: &Token, from: usize, to: usize) -> Span {
+    let mut start = tok.span.start.clone();
+    for c in tok.imm_s[..from].chars() {
+        start.advance(c);
+    }
+    let mut end = start.clone();
+    for c in tok.imm_s[from..to].chars() {
+        end.advance(c);
+    }
+    return Span::new(start, end);
+}
+
+/// An element whose children are being read.
+struct Open {
+    id: NodeId,
+    name: String,
+    // 開始タグの位置
+    tag: Span,
+}
+
+/// What `Parser::parse_recovering` made of the tokens.
+#[derive(Debug)]
+pub struct Recovered {
+    pub nodes: Vec<Node>,
+    pub diagnostics: Vec<Diagnostic>,
+}
 
 pub struct Parser {
-    token: Option<Box<Token>>,
+    tokens: Vec<Token>,
+    // 次に読むトークンの位置
+    index: usize,
+    // 最後に読んだトークンの終わり
+    last_end: Position,
+    // 開いている要素。入れ子を再帰でなくこのスタックで辿る
+    open: Vec<Open>,
+    // 開いている要素のうち、終了タグを省略できるものの数 (名前は小文字)
+    optional_open: HashMap<String, usize>,
+    // 回復モードなら、エラーで止まらずに記録して続ける
+    recover: bool,
+    diagnostics: Vec<Diagnostic>,
+    // 読んだノードはここに直接足していく
+    doc: Document,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        return Parser::new();
+    }
 }
 
 impl Parser {
     pub fn new() -> Parser {
-        return Parser { token: None };
+        return Parser {
+            tokens: vec![],
+            index: 0,
+            last_end: Position::default(),
+            open: vec![],
+            optional_open: HashMap::new(),
+            recover: false,
+            diagnostics: vec![],
+            doc: Document::new(),
+        };
     }
 
-    fn current(&self) -> Box<Token> {
-        return self.token.clone().unwrap();
+    fn current(&self) -> &Token {
+        return &self.tokens[self.index];
     }
 
     fn is_eof(&self) -> bool {
         return self.current().kind == TokenKind::Eof;
     }
 
-    fn consume(&mut self) -> Option<Box<Token>> {
-        let tok = self.current();
-        self.token = self.current().next;
-        return Some(tok);
+    /// Span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: Position) -> Span {
+        return Span::new(start, self.last_end.clone());
     }
 
-    fn consume_kind(&mut self, kind: TokenKind) -> Option<Box<Token>> {
-        if self.current().kind == kind {
-            let tok = self.current();
-            self.token = self.current().next;
-            return Some(tok);
+    fn consume(&mut self) -> Option<Token> {
+        let tok = self.current().clone();
+        // Eof より先には進まない
+        if !self.is_eof() {
+            self.index += 1;
         }
-        return None;
+        self.last_end = tok.span.end.clone();
+        return Some(tok);
     }
 
     fn expect_kind(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
         if self.current().kind == kind {
-            let cur = self.current();
-            self.token = cur.next.clone();
-            return Ok(*cur);
+            return Ok(self.consume().unwrap());
         }
         return Err(ParseError::UnexpectedToken {
             expected: kind,
-            found: *self.current(),
+            found: Box::new(self.current().clone()),
         });
     }
 
-    fn expect_text(&mut self, text: String, case_sensitive: bool) -> Result<(), ParseError> {
-        return match self.expect_kind(TokenKind::Text) {
-            Err(error) => Err(error),
-            Ok(tok) => {
-                if *&case_sensitive && (tok.imm_s == text) {
-                    return Ok(());
-                }
-                if *&!case_sensitive && (tok.imm_s.to_lowercase() == text) {
-                    return Ok(());
-                }
-                return Err(ParseError::UnexpectedText {
-                    expected: text,
-                    found: tok.imm_s,
-                });
-            }
+    /// Whether an element `name` is open. Tag names are kept as written, so
+    /// `</DIV>` closes `<div>`.
+    fn is_open(&self, name: &str) -> bool {
+        return self
+            .open
+            .iter()
+            .any(|open| open.name.eq_ignore_ascii_case(name));
+    }
+
+    /// Gives up on `err`, or in recovering mode notes it and carries on.
+    fn report(&mut self, err: ParseError) -> Result<(), ParseError> {
+        if !self.recover {
+            return Err(err);
+        }
+        self.diagnostics.push(Diagnostic::new(err));
+        return Ok(());
+    }
+
+    /// Why the element `name` can't be closed by the current token.
+    fn unclosed(&self, name: &str) -> ParseError {
+        let tok = self.current();
+        if tok.kind == TokenKind::EndTag {
+            return ParseError::TagMissMatch {
+                open: name.to_string(),
+                close: tok.imm_s.clone(),
+                span: tok.name_span(),
+            };
+        }
+        return ParseError::UnexpectedToken {
+            expected: TokenKind::EndTag,
+            found: Box::new(tok.clone()),
         };
     }
 
-    fn parse_text(&mut self) -> Result<Option<Box<Node>>, ParseError> {
-        let mut text: String = "".to_string();
+    /// An end tag with no open element to close, kept as an error node.
+    fn parse_stray_end_tag(&mut self, parent: Option<NodeId>) -> Result<(), ParseError> {
+        let tok = self.expect_kind(TokenKind::EndTag)?;
+        let err = match self.open.last() {
+            Some(open) => ParseError::TagMissMatch {
+                open: open.name.clone(),
+                close: tok.imm_s.clone(),
+                span: tok.name_span(),
+            },
+            None => ParseError::UnexpectedToken {
+                expected: TokenKind::Eof,
+                found: Box::new(tok.clone()),
+            },
+        };
+        self.report(err)?;
 
-        while !self.is_eof() {
-            match self.consume_kind(TokenKind::Text) {
-                None => break,
-                Some(tok) => text += &*tok.imm_s,
-            }
+        let nd = Node::Error {
+            text: tok.raw,
+            span: tok.span,
+        };
+        self.doc.append(parent, nd);
+        return Ok(());
+    }
+
+    fn parse_text(&mut self, parent: Option<NodeId>) -> Result<(), ParseError> {
+        let mut toks: Vec<Token> = vec![];
+        while matches!(self.current().kind, TokenKind::Text | TokenKind::CharRef) {
+            toks.push(self.consume().unwrap());
+        }
+        if toks.is_empty() {
+            return Err(ParseError::UnexpectedToken {
+                expected: TokenKind::Text,
+                found: Box::new(self.current().clone()),
+            });
         }
 
-        let nd = Node::new(NodeKind::Text, None, None, None, None, text);
-        return Ok(Some(Box::from(nd)));
-    }
-
-    fn parse_decl_tag(&mut self) -> Result<Option<Box<Node>>, ParseError> {
-        // doctype or comment
-
-        // comment
-        if self.consume_kind(TokenKind::Hyphen) != None {
-            match self.expect_kind(TokenKind::Hyphen) {
-                Err(error) => return Err(error),
-                Ok(_) => {
-                    let mut comment: String = "".to_string();
-                    while !self.is_eof() {
-                        if self.consume_kind(TokenKind::Hyphen) != None {
-                            if self.consume_kind(TokenKind::Hyphen) != None {
-                                if self.consume_kind(TokenKind::TagEnd) != None {
-                                    // 終わり
-                                    return Ok(Some(Box::from(Node::new(
-                                        NodeKind::CommentTag,
-                                        None,
-                                        None,
-                                        None,
-                                        None,
-                                        comment,
-                                    ))));
-                                } else {
-                                    comment += "--";
-                                    continue;
-                                }
-                            } else {
-                                comment += "-";
-                                continue;
-                            }
-                        }
-
-                        if self.consume_kind(TokenKind::Whitespace) != None {
-                            comment += " ";
-                            continue;
-                        }
-
-                        comment += &*self.consume().unwrap().imm_s
-                    }
-                }
+        // 前後の空白はノードに含めない
+        let raw: String = toks.iter().map(|t| t.imm_s.as_str()).collect();
+        let from = raw.len() - raw.trim_start_matches(is_white).len();
+        let to = raw.trim_end_matches(is_white).len();
+        if from >= to {
+            return Ok(());
+        }
+
+        // テキストと文字参照に分けておく
+        let mut parts: Vec<TextPart> = vec![];
+        let mut at: usize = 0;
+        for tok in &toks {
+            let (tok_from, tok_to) = (at, at + tok.imm_s.len());
+            at = tok_to;
+            if tok_to <= from || to <= tok_from {
+                continue;
             }
+            let (cut_from, cut_to) = (from.max(tok_from) - tok_from, to.min(tok_to) - tok_from);
+            let span = sub_span(tok, cut_from, cut_to);
+            parts.push(match tok.kind {
+                TokenKind::CharRef => TextPart::CharRef {
+                    raw: tok.imm_s.clone(),
+                    decoded: tok.decoded.clone(),
+                    span,
+                },
+                _ => TextPart::Plain {
+                    text: tok.imm_s[cut_from..cut_to].to_string(),
+                    span,
+                },
+            });
         }
 
-        // consume doctype
-        match self.expect_text("doctype".to_string(), false) {
-            Ok(_) => (),
-            Err(err) => return Err(err),
+        let span_of = |part: &TextPart| -> Span {
+            return match part {
+                TextPart::Plain { span, .. } | TextPart::CharRef { span, .. } => span.clone(),
+            };
+        };
+        let start = span_of(parts.first().unwrap()).start;
+        let end = span_of(parts.last().unwrap()).end;
+        let nd = Node::Text(Text {
+            text: raw[from..to].to_string(),
+            parts,
+            span: Span::new(start, end),
+        });
+        self.doc.append(parent, nd);
+        return Ok(());
+    }
+
+    /// The contents of a raw text element, as one node holding the source
+    /// exactly as written.
+    fn parse_raw_text(&mut self, parent: NodeId) {
+        let start = self.current().span.start.clone();
+        let mut text: String = "".to_string();
+        while matches!(self.current().kind, TokenKind::Text | TokenKind::CharRef) {
+            text += &*self.consume().unwrap().imm_s;
         }
-        // consume ws
-        match self.expect_kind(TokenKind::Whitespace) {
-            Ok(_) => (),
-            Err(err) => return Err(err),
+        if text.is_empty() {
+            return;
         }
 
-        // type: eg. html
-        let doctype_node = match self.expect_kind(TokenKind::Text) {
-            Ok(tok) => Node::new(
-                NodeKind::DoctypeTag,
-                None,
-                None,
-                None,
-                None,
-                tok.imm_s.to_string().to_lowercase(),
-            ),
-            Err(err) => return Err(err),
+        let nd = Node::RawText {
+            text,
+            span: self.span_from(start),
         };
+        self.doc.append(Some(parent), nd);
+    }
 
-        // consume ">"
-        match self.expect_kind(TokenKind::TagEnd) {
-            Ok(_) => (),
-            Err(err) => return Err(err),
+    fn parse_comment(&mut self, parent: Option<NodeId>) -> Result<(), ParseError> {
+        let tok = self.expect_kind(TokenKind::Comment)?;
+        // 閉じられていないコメントは入力の最後までを飲み込んでいるので、そのまま写す
+        if tok.unclosed {
+            self.report(ParseError::UnclosedComment {
+                span: tok.span.clone(),
+            })?;
+            let nd = Node::Error {
+                text: tok.raw,
+                span: tok.span,
+            };
+            self.doc.append(parent, nd);
+            return Ok(());
+        }
+        let raw = if tok.raw == format!("<!--{}-->", tok.imm_s) {
+            None
+        } else {
+            Some(tok.raw)
         };
-
-        return Ok(Some(Box::from(doctype_node)));
+        let nd = Node::Comment {
+            text: tok.imm_s,
+            raw,
+            span: tok.span,
+        };
+        self.doc.append(parent, nd);
+        return Ok(());
     }
 
-    fn parse_tag_parameters(&mut self) -> Result<Option<Box<Node>>, ParseError> {
-        let mut children: Vec<Option<Box<Node>>> = vec![];
+    fn parse_doctype(&mut self, parent: Option<NodeId>) -> Result<(), ParseError> {
+        // type: eg. html
+        let tok = self.expect_kind(TokenKind::Doctype)?;
+        let nd = Node::Doctype {
+            text: tok.imm_s,
+            span: tok.span,
+        };
+        self.doc.append(parent, nd);
+        return Ok(());
+    }
 
-        while !self.is_eof() {
-            self.consume_kind(TokenKind::Whitespace);
-            // ">" or "/" がきたら中止
-            // 最後の処理はtag_bodyに任せるので、consumeしない
-            if self.current().kind == TokenKind::TagEnd || self.current().kind == TokenKind::Slash {
-                break;
-            }
-            // whitespace あるかも
-            self.consume_kind(TokenKind::Whitespace);
-
-            // param = value
-            // param
-            let param_name = match self.consume_kind(TokenKind::Text) {
-                Some(tok) => tok,
-                None => {
-                    return Err(ParseError::UnexpectedToken {
-                        expected: TokenKind::Text,
-                        found: *self.current(),
-                    })
-                }
+    /// Reads a start tag. An element with contents is left open for
+    /// `parse_` to add the children to.
+    fn parse_tag(&mut self, parent: Option<NodeId>) -> Result<(), ParseError> {
+        let open = self.expect_kind(TokenKind::StartTag)?;
+        let name = open.imm_s.clone();
+        // 書きかけのタグは属性を読み違えているので、書かれたまま写す
+        if open.unclosed {
+            self.report(ParseError::UnclosedTag {
+                name,
+                span: open.span.clone(),
+            })?;
+            let nd = Node::Error {
+                text: open.raw,
+                span: open.span,
             };
-            // =
-            match self.expect_kind(TokenKind::Assign) {
-                Ok(_) => {}
-                Err(err) => return Err(err),
-            }
-            // value maybe string
-            let value: Token;
-            match self.expect_kind(TokenKind::String) {
-                Ok(v) => value = v,
-                Err(err) => return Err(err),
+            self.doc.append(parent, nd);
+            return Ok(());
+        }
+        let mut element = Element::new(&name);
+        element.attrs = open.attrs.clone();
+        element.self_closing = open.self_closing;
+
+        // Solo tag: <x />, or a void element like <br>
+        if element.is_solo() {
+            element.span = open.span;
+            self.doc.append(parent, Node::Element(element));
+            return Ok(());
+        }
+        // 子を足す先になるので、先に入れておいて終わりの位置は後で決める
+        let id = self.doc.append(parent, Node::Element(element));
+
+        // script, style, textarea, title の中身はそのまま一つのノードにする
+        if is_raw_text(&name) {
+            self.parse_raw_text(id);
+            // <plaintext> には終了タグがなく、最後まで続く
+            let end_omitted = self.is_eof();
+            if !end_omitted {
+                self.expect_kind(TokenKind::EndTag)?;
+            } else if !name.eq_ignore_ascii_case("plaintext") {
+                self.report(self.unclosed(&name))?;
             }
+            self.close(id, self.span_from(open.span.start), end_omitted);
+            return Ok(());
+        }
 
-            let lhs = Node::new(Identifier, None, None, None, None, param_name.imm_s);
-            let rhs = Node::new(VString, None, None, None, None, value.imm_s);
-
-            children.push(Some(Box::from(Node::new(
-                NodeKind::Parameter,
-                Some(Box::from(lhs)),
-                Some(Box::from(rhs)),
-                None,
-                None,
-                "".to_string(),
-            ))));
+        // 子は parse_ が読み、終わったら close_open で閉じる
+        self.push_open(Open {
+            id,
+            name,
+            tag: open.span,
+        });
+        return Ok(());
+    }
 
-            self.consume_kind(TokenKind::Whitespace);
+    fn push_open(&mut self, open: Open) {
+        if has_optional_end_tag(&open.name) {
+            *self
+                .optional_open
+                .entry(open.name.to_ascii_lowercase())
+                .or_insert(0) += 1;
         }
+        self.open.push(open);
+    }
 
-        if children.len() == 0 {
-            return Ok(None);
+    fn pop_open(&mut self) -> Open {
+        let open = self.open.pop().unwrap();
+        if let Some(n) = self.optional_open.get_mut(&open.name.to_ascii_lowercase()) {
+            *n -= 1;
         }
+        return open;
+    }
 
-        return Ok(Some(Box::from(Node::new(
-            NodeKind::Parameters,
-            None,
-            None,
-            Some(children),
-            None,
-            "".to_string(),
-        ))));
-    }
-
-    // fn parse_tag_body(&mut self, is_close: bool) -> Result<Option<Box<Node>>, ParseError> {
-    //
-    //
-    //     // >
-    //     if self.consume_kind(TokenKind::TagEnd) != None {
-    //         // close tag </...>
-    //         if is_close {
-    //             return Ok(Some(Box::from(Node::new(
-    //                 CloseTag, None, None, params, tag_name,
-    //             ))));
-    //         }
-    //         // open tag <...>
-    //         return Ok(Some(Box::from(Node::new(
-    //             OpenTag, None, None, params, tag_name,
-    //         ))));
-    //     }
-    //
-    //     return Err(ParseError::Unknown);
-    // }
-
-    fn parse_tag(&mut self) -> Result<Option<Box<Node>>, ParseError> {
-        if self.consume_kind(TokenKind::Exclamation) != None {
-            return self.parse_decl_tag();
+    /// The open element that the start tag `next` ends without an end tag,
+    /// if any. The elements opened inside it end along with it.
+    fn implied_end(&self, next: &str) -> Option<usize> {
+        // 閉じられる要素が一つも開いていなければ、深い入れ子を辿らずに済ませる
+        if !self
+            .optional_open
+            .iter()
+            .any(|(name, n)| *n > 0 && is_closed_by(name, next))
+        {
+            return None;
         }
-
-        if self.current().kind == TokenKind::Slash {
-            return Ok(None);
+        for (i, open) in self.open.iter().enumerate().rev() {
+            if is_closed_by(&open.name, next) {
+                return Some(i);
+            }
+            if stops_implied_end(&open.name, next) {
+                return None;
+            }
         }
+        return None;
+    }
 
-        let tag_name = match self.expect_kind(TokenKind::Text) {
-            Ok(tok) => tok.imm_s.to_lowercase(),
-            Err(err) => return Err(err),
-        };
-
-        // wsが入っている確率が高いので消しておく
-        self.consume_kind(TokenKind::Whitespace);
-
-        // parameters
-        let params = match self.parse_tag_parameters() {
-            Ok(nd) => nd,
-            Err(err) => return Err(err),
-        };
-
-        // wsが入っている確率が高いので消しておく
-        self.consume_kind(TokenKind::Whitespace);
+    /// Ends the open elements from `depth` in, their end tags left out.
+    fn close_implied(&mut self, depth: usize) {
+        while self.open.len() > depth {
+            let Open { id, tag, .. } = self.pop_open();
+            self.close_omitted(id, tag);
+        }
+    }
 
-        // Solo tag
-        if self.consume_kind(TokenKind::Slash) != None {
-            return match self.expect_kind(TokenKind::TagEnd) {
-                Ok(_) => Ok(Some(Box::from(Node::new(
-                    SoloTag, None, None, None, params, tag_name,
-                )))),
-                Err(err) => Err(err),
-            };
+    /// Whether a `<p>` is open and not hidden behind a `<button>`, `<table>`
+    /// or the like.
+    fn has_p_in_button_scope(&self) -> bool {
+        if self.optional_open.get("p").is_none_or(|n| *n == 0) {
+            return false;
+        }
+        for open in self.open.iter().rev() {
+            if open.name.eq_ignore_ascii_case("p") {
+                return true;
+            }
+            if is_button_scope(&open.name) {
+                return false;
+            }
         }
+        return false;
+    }
 
-        // ">"
-        match self.expect_kind(TokenKind::TagEnd) {
-            Ok(_) => {}
-            Err(err) => return Err(err),
+    /// A `</p>` with no `<p>` to end, which stands for an empty `p` as it
+    /// does in browsers.
+    fn parse_lone_p_end(&mut self, parent: Option<NodeId>) -> Result<(), ParseError> {
+        let tok = self.expect_kind(TokenKind::EndTag)?;
+        let mut element = Element::new(&tok.imm_s);
+        element.span = tok.span;
+        self.doc.append(parent, Node::Element(element));
+        return Ok(());
+    }
+
+    /// Closes the innermost open element, at its end tag if the current
+    /// token is one and where its contents end if not.
+    fn close_open(&mut self) -> Result<(), ParseError> {
+        let Open { id, name, tag } = self.pop_open();
+
+        // 終了タグを省略できる要素は、閉じタグ以外が来たらそこで終わる:
+        // <li>a<li>b, <ul><li>a</ul>
+        // 回復モードでは、閉じられていない要素もそこで閉じたことにする
+        let is_own_end = self.current().kind == TokenKind::EndTag
+            && self.current().imm_s.eq_ignore_ascii_case(&name);
+        if !is_own_end {
+            if !has_optional_end_tag(&name) {
+                self.report(self.unclosed(&name))?;
+            }
+            self.close_omitted(id, tag);
+            return Ok(());
         }
 
-        let children: Option<Vec<Option<Box<Node>>>> = match self.parse_() {
-            Ok(c) => c,
-            Err(err) => return Err(err),
-        };
+        // closing tag
+        self.expect_kind(TokenKind::EndTag)?;
 
-        // "/" of close tag
-        match self.expect_kind(TokenKind::Slash) {
-            Ok(_) => {}
-            Err(err) => return Err(err),
-        };
+        self.close(id, self.span_from(tag.start), false);
+        return Ok(());
+    }
 
-        // closing tag name
-        let close_tag_name = match self.expect_kind(TokenKind::Text) {
-            Ok(tok) => tok.imm_s.to_lowercase(),
-            Err(err) => return Err(err),
+    /// Ends the element `id`, which starts with `tag`, where its contents end.
+    fn close_omitted(&mut self, id: NodeId, tag: Span) {
+        let end = match self.doc.children(id).last() {
+            Some(last) => self.doc.node(*last).span().end.clone(),
+            None => tag.end.clone(),
         };
+        self.close(id, Span::new(tag.start, end), true);
+    }
 
-        match self.expect_kind(TokenKind::TagEnd) {
-            Ok(_) => {}
-            Err(err) => return Err(err),
-        }
-
-        // tag miss match: eg. <xxx></yyy>
-        if tag_name.clone() != close_tag_name.clone() {
-            return Err(ParseError::TagMissMatch {
-                open: tag_name,
-                close: close_tag_name,
-            });
+    /// Sets where the element `id`, added before its children, ends.
+    fn close(&mut self, id: NodeId, span: Span, end_omitted: bool) {
+        if let Node::Element(element) = self.doc.node_mut(id) {
+            element.span = span;
+            element.end_omitted = end_omitted;
         }
+    }
 
-        return Ok(Some(Box::from(Node::new(
-            Tag,
-            None,
-            None,
-            children,
-            params,
-            tag_name.to_string(),
-        ))));
-    }
-
-    fn parse_(&mut self) -> Result<Option<Vec<Option<Box<Node>>>>, ParseError> {
-        let mut nodes: Vec<Option<Box<Node>>> = Vec::new();
-        while !self.is_eof() {
-            self.consume_kind(TokenKind::Whitespace);
-            let nd_result = match self.consume_kind(TokenKind::TagBegin) {
-                Some(_) => self.parse_tag(),
-                None => self.parse_text(),
-            };
-            // テキスト、あるいはタグのパースは成功しましたか?
-            match nd_result {
-                Ok(nd) => match nd {
-                    Some(n) => nodes.push(Some(n)),
-                    None => break,
+    fn parse_(&mut self) -> Result<(), ParseError> {
+        loop {
+            let parent = self.open.last().map(|open| open.id);
+            match self.current().kind {
+                TokenKind::Text | TokenKind::CharRef => self.parse_text(parent)?,
+                TokenKind::Comment => self.parse_comment(parent)?,
+                TokenKind::Doctype => self.parse_doctype(parent)?,
+                TokenKind::StartTag => match self.implied_end(&self.current().imm_s) {
+                    // 開いている要素を暗黙に閉じる開始タグ: <li>a<li>b, <li><b>a<li>b
+                    Some(depth) => self.close_implied(depth),
+                    None => self.parse_tag(parent)?,
                 },
-                Err(err) => return Err(err),
+                TokenKind::EndTag
+                    if self.current().imm_s.eq_ignore_ascii_case("p")
+                        && !self.has_p_in_button_scope() =>
+                {
+                    self.parse_lone_p_end(parent)?
+                }
+                // 開いていない要素の閉じタグは、回復モードなら読み飛ばす
+                TokenKind::EndTag if self.recover && !self.is_open(&self.current().imm_s) => {
+                    self.parse_stray_end_tag(parent)?
+                }
+                // 開いている要素がなければ、閉じタグは parse_document で確かめる
+                TokenKind::EndTag | TokenKind::Eof if self.open.is_empty() => break,
+                TokenKind::EndTag | TokenKind::Eof => self.close_open()?,
             }
-            self.consume_kind(TokenKind::Whitespace);
         }
 
-        if nodes.len() == 0 {
-            return Ok(None);
-        }
+        return Ok(());
+    }
 
-        return Ok(Some(nodes));
+    /// Parses `tokens`, which must end with an `Eof` token as produced by
+    /// the tokenizer.
+    pub fn parse(&mut self, tokens: Vec<Token>) -> Result<Vec<Node>, ParseError> {
+        return Ok(self.parse_document(tokens)?.into_nodes());
     }
 
-    pub fn parse(
-        &mut self,
-        token: Box<Token>,
-    ) -> Result<Option<Vec<Option<Box<Node>>>>, ParseError> {
-        self.token = Some(token);
-        match self.parse_() {
-            Ok(n) => return Ok(n),
-            Err(err) => return Err(err),
+    /// Like `parse`, but gives the tree as a `Document`, which can also be
+    /// walked upwards and sideways.
+    pub fn parse_document(&mut self, tokens: Vec<Token>) -> Result<Document, ParseError> {
+        self.reset(tokens, false);
+        self.parse_()?;
+
+        // 対応する開始タグのない閉じタグ
+        if !self.is_eof() {
+            return Err(ParseError::UnexpectedToken {
+                expected: TokenKind::Eof,
+                found: Box::new(self.current().clone()),
+            });
         }
+        return Ok(std::mem::take(&mut self.doc));
+    }
+
+    /// Like `parse`, but doesn't stop at the first error. Unclosed elements
+    /// end where the parser gives up on them and end tags that close nothing
+    /// become `Error` nodes; every such problem is returned with its span.
+    pub fn parse_recovering(&mut self, tokens: Vec<Token>) -> Recovered {
+        self.reset(tokens, true);
+        // 回復モードの report はエラーを返さないので、最後まで読める
+        if let Err(err) = self.parse_() {
+            self.diagnostics.push(Diagnostic::new(err));
+        }
+        return Recovered {
+            nodes: std::mem::take(&mut self.doc).into_nodes(),
+            diagnostics: std::mem::take(&mut self.diagnostics),
+        };
+    }
+
+    fn reset(&mut self, tokens: Vec<Token>, recover: bool) {
+        self.tokens = tokens;
+        self.index = 0;
+        self.last_end = Position::default();
+        self.open = vec![];
+        self.optional_open = HashMap::new();
+        self.recover = recover;
+        self.diagnostics = vec![];
+        self.doc = Document::new();
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::parse::node::{Element, Node, TextPart};
     use crate::parse::parser::Parser;
+    use crate::tokenize::position::Span;
     use crate::tokenize::tokenizer;
+    fn element(node: &Node) -> &Element {
+        return match node {
+            Node::Element(element) => element,
+            other => panic!("expected an element, got {:?}", other),
+        };
+    }
+
     #[test]
     fn parse_only_decl() {
         let mut tokenizer_ = tokenizer::Tokenizer::new("<!doctype html><!-- hello, w--orld -->");
@@ -413,4 +599,333 @@ mod test {
         let nodes = parser_.parse(tok);
         println!("{:#?}", nodes)
     }
+
+    #[test]
+    fn nodes_carry_source_spans() {
+        let html = "<div>\n  <a href=\"x\">hi</a>\n</div>";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+
+        let mut parser_ = Parser::new();
+        let nodes = parser_.parse(tok).unwrap();
+        let slice = |span: &Span| &html[span.start.at_byte..span.end.at_byte];
+
+        let div = element(&nodes[0]);
+        assert_eq!(slice(&div.span), html);
+
+        let a = element(&div.children[0]);
+        assert_eq!(slice(&a.span), "<a href=\"x\">hi</a>");
+        assert_eq!(a.span.start.line_no, 2);
+        assert_eq!(a.span.start.at_line, 2);
+
+        let attr = &a.attrs[0];
+        assert_eq!(slice(&attr.name_span), "href");
+        assert_eq!(slice(attr.value_span.as_ref().unwrap()), "\"x\"");
+
+        assert_eq!(slice(a.children[0].span()), "hi");
+    }
+
+    #[test]
+    fn mismatch_error_points_at_closing_name() {
+        let html = "<b>\n</q>";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+
+        let err = Parser::new().parse(tok).unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!(&html[span.start.at_byte..span.end.at_byte], "q");
+        assert_eq!(span.start.line_no, 2);
+    }
+
+    #[test]
+    fn recovering_parse_reports_every_error() {
+        let html = "<div><b>x</q></div></span><i>y";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+
+        let recovered = Parser::new().parse_recovering(tok);
+        let found: Vec<(&str, String)> = recovered
+            .diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    &html[d.span.start.at_byte..d.span.end.at_byte],
+                    d.error.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "q",
+                    "opening tag & closing tag name was miss matched (open: \"b\", close: \"q\")"
+                        .to_string()
+                ),
+                (
+                    "div",
+                    "opening tag & closing tag name was miss matched (open: \"b\", close: \"div\")"
+                        .to_string()
+                ),
+                (
+                    "</span>",
+                    "unexpected token (expect: Eof, found EndTag)".to_string()
+                ),
+                (
+                    "",
+                    "unexpected token (expect: EndTag, found Eof)".to_string()
+                ),
+            ]
+        );
+
+        // 読めなかった閉じタグはエラーのノードになり、閉じられていない要素もそこで終わる
+        let nodes = recovered.nodes;
+        let slices: Vec<&str> = nodes
+            .iter()
+            .map(|n| &html[n.span().start.at_byte..n.span().end.at_byte])
+            .collect();
+        assert_eq!(slices, vec!["<div><b>x</q></div>", "</span>", "<i>y"]);
+        assert!(matches!(&nodes[1], Node::Error { text, .. } if text == "</span>"));
+
+        let b = element(&element(&nodes[0]).children[0]);
+        assert!(b.end_omitted);
+        assert!(matches!(
+            b.children.as_slice(),
+            [Node::Text(_), Node::Error { .. }]
+        ));
+    }
+
+    #[test]
+    fn parse_long_input() {
+        // 長い入力でもスタックを溢れさせない
+        let html = "<p class=\"a\">hello</p>\n".repeat(50_000);
+        let mut tokenizer_ = tokenizer::Tokenizer::new(&html);
+        let tok = tokenizer_.tokenize();
+
+        let mut parser_ = Parser::new();
+        let nodes = parser_.parse(tok).unwrap();
+        assert_eq!(nodes.len(), 50_000);
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        // 入れ子が深くてもスタックを溢れさせない
+        let depth = 100_000;
+        let html = "<div>".repeat(depth) + "x" + &"</div>".repeat(depth);
+        let tok = tokenizer::Tokenizer::new(&html).tokenize();
+
+        let nodes = Parser::new().parse(tok.clone()).unwrap();
+        let mut deepest = &nodes[0];
+        let mut levels = 1;
+        while let Node::Element(element) = deepest {
+            deepest = &element.children[0];
+            levels += 1;
+        }
+        assert_eq!(levels, depth + 1);
+        assert!(matches!(deepest, Node::Text(text) if text.text == "x"));
+
+        let document = Parser::new().parse_document(tok).unwrap();
+        assert_eq!(document.len(), depth + 1);
+        let root = document.to_node(document.roots()[0]);
+        assert_eq!(root.span().end.at_byte, html.len());
+
+        // 閉じられないまま終わっても同じ
+        let tok = tokenizer::Tokenizer::new(&html[..html.len() - 6 * depth]).tokenize();
+        let recovered = Parser::new().parse_recovering(tok);
+        assert_eq!(recovered.diagnostics.len(), depth);
+    }
+
+    #[test]
+    fn text_keeps_char_refs_apart() {
+        let html = "<p>\n  a&amp;b &#x3042;\n</p>";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+
+        let nodes = Parser::new().parse(tok).unwrap();
+        let text = match &element(&nodes[0]).children[0] {
+            Node::Text(text) => text,
+            other => panic!("expected text, got {:?}", other),
+        };
+        assert_eq!(text.text, "a&amp;b &#x3042;");
+        assert_eq!(
+            &html[text.span.start.at_byte..text.span.end.at_byte],
+            "a&amp;b &#x3042;"
+        );
+
+        let parts: Vec<(String, Option<String>)> = text
+            .parts
+            .iter()
+            .map(|part| match part {
+                TextPart::Plain { text, .. } => (text.clone(), None),
+                TextPart::CharRef { raw, decoded, .. } => (raw.clone(), Some(decoded.clone())),
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("a".to_string(), None),
+                ("&amp;".to_string(), Some("&".to_string())),
+                ("b ".to_string(), None),
+                ("&#x3042;".to_string(), Some("あ".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn void_elements_have_no_children() {
+        let html = "<head><meta charset=\"utf-8\"><link rel=x></head><p>a<br>b<img src=x></p>";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+
+        let nodes = Parser::new().parse(tok).unwrap();
+        let kinds = |node: &Node| -> Vec<(String, bool)> {
+            return element(node)
+                .children
+                .iter()
+                .map(|n| match n {
+                    Node::Element(e) => (e.name.clone(), e.is_solo() && e.children.is_empty()),
+                    Node::Text(t) => (t.text.clone(), false),
+                    other => panic!("unexpected {:?}", other),
+                })
+                .collect();
+        };
+        assert_eq!(
+            kinds(&nodes[0]),
+            vec![("meta".to_string(), true), ("link".to_string(), true)]
+        );
+        assert_eq!(
+            kinds(&nodes[1]),
+            vec![
+                ("a".to_string(), false),
+                ("br".to_string(), true),
+                ("b".to_string(), false),
+                ("img".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn implied_end_tags() {
+        let html = "<ul><li>a<li>b</ul>\
+                    <table><tr><td>1<td>2<tr><td>3</table>\
+                    <dl><dt>t<dd>d</dl>\
+                    <div><p>x<p>y<div>z</div></div>\
+                    <ul><li><b>a<li>b</ul>\
+                    <p><span>x<div>y</div>\
+                    <p>x<div>z</div></p>\
+                    <p>a<button><div>b</div></button>c</p>\
+                    <p>end";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+        let nodes = Parser::new().parse(tok).unwrap();
+
+        // 子の名前 (テキストは中身) を入れ子のまま並べる
+        fn shape(node: &Node) -> String {
+            return match node {
+                Node::Text(text) => text.text.clone(),
+                Node::Element(e) => {
+                    let children: Vec<String> = e.children.iter().map(shape).collect();
+                    format!("{}({})", e.name, children.join(" "))
+                }
+                other => panic!("unexpected {:?}", other),
+            };
+        }
+        let shapes: Vec<String> = nodes.iter().map(shape).collect();
+        assert_eq!(
+            shapes,
+            vec![
+                "ul(li(a) li(b))",
+                "table(tr(td(1) td(2)) tr(td(3)))",
+                "dl(dt(t) dd(d))",
+                "div(p(x) p(y) div(z))",
+                "ul(li(b(a)) li(b))",
+                "p(span(x))",
+                "div(y)",
+                "p(x)",
+                "div(z)",
+                "p()",
+                "p(a button(div(b)) c)",
+                "p(end)",
+            ]
+        );
+
+        let ul = element(&nodes[0]);
+        let li = element(&ul.children[0]);
+        assert!(li.end_omitted && !ul.end_omitted);
+        assert_eq!(&html[li.span.start.at_byte..li.span.end.at_byte], "<li>a");
+    }
+
+    #[test]
+    fn required_end_tags_still_mismatch() {
+        let mut tokenizer_ = tokenizer::Tokenizer::new("<div><span>a</div>");
+        let tok = tokenizer_.tokenize();
+        assert!(Parser::new().parse(tok).is_err());
+    }
+
+    #[test]
+    fn raw_text_is_one_node() {
+        let script = "\n  if (a < b && c) { s = \"</div>\"; }\n";
+        let html = format!(
+            "<script>{}</script><textarea> a &lt; b\n</textarea>",
+            script
+        );
+        let mut tokenizer_ = tokenizer::Tokenizer::new(&html);
+        let tok = tokenizer_.tokenize();
+        let nodes = Parser::new().parse(tok).unwrap();
+
+        let contents: Vec<(String, String)> = nodes
+            .iter()
+            .map(|n| match element(n).children.as_slice() {
+                [Node::RawText { text, span }] => {
+                    let source = html[span.start.at_byte..span.end.at_byte].to_string();
+                    (text.clone(), source)
+                }
+                other => panic!("expected one raw text node, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                (script.to_string(), script.to_string()),
+                (" a &lt; b\n".to_string(), " a &lt; b\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_document_links_parents_and_siblings() {
+        let html = "<ul><li>a<li><b>b</b></ul><p>c</p>";
+        let mut tokenizer_ = tokenizer::Tokenizer::new(html);
+        let tok = tokenizer_.tokenize();
+        let document = Parser::new().parse_document(tok).unwrap();
+        let slice = |id| {
+            let span = document.node(id).span();
+            return &html[span.start.at_byte..span.end.at_byte];
+        };
+
+        let ul = document.roots()[0];
+        let first = document.children(ul)[0];
+        let second = document.next_sibling(first).unwrap();
+        assert_eq!(slice(second), "<li><b>b</b>");
+        assert_eq!(document.next_sibling(second), None);
+        assert_eq!(slice(document.next_sibling(ul).unwrap()), "<p>c</p>");
+
+        let b = document
+            .descendants(ul)
+            .find(|id| slice(*id) == "b")
+            .unwrap();
+        let up: Vec<&str> = document.ancestors(b).map(slice).collect();
+        assert_eq!(
+            up,
+            vec!["<b>b</b>", "<li><b>b</b>", "<ul><li>a<li><b>b</b></ul>"]
+        );
+
+        // 同じ木を持ち主のあるノードでも返せる
+        assert_eq!(
+            document.to_nodes(),
+            Parser::new()
+                .parse(tokenizer::Tokenizer::new(html).tokenize())
+                .unwrap()
+        );
+    }
 }
@@ -1,4 +1,6 @@
+pub mod charref;
+pub mod kind;
+pub mod position;
+pub mod state;
 pub mod token;
 pub mod tokenizer;
-pub mod position;
-pub mod kind;
\ No newline at end of file
@@ -0,0 +1,216 @@
+use entities::ENTITIES;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// A character reference (`&amp;`, `&#38;`, `&#x26;`) found at the start of
+/// some input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharRef {
+    /// Length of the reference as written, in bytes, including the `&`.
+    pub len: usize,
+    /// The one or two code points it stands for.
+    pub decoded: String,
+}
+
+/// Every named reference of HTML, by name without the `&`. Some names also
+/// appear without the trailing `;` for compatibility (`amp`, `nbsp`, ...).
+fn names() -> &'static HashMap<&'static str, &'static str> {
+    static NAMES: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    return NAMES.get_or_init(|| {
+        return ENTITIES
+            .iter()
+            .map(|e| (&e.entity[1..], e.characters))
+            .collect();
+    });
+}
+
+/// The name to write for some characters, e.g. `"&nbsp;"` for U+00A0.
+pub fn name_of(decoded: &str) -> Option<&'static str> {
+    static PREFERRED: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    let preferred = PREFERRED.get_or_init(|| {
+        let mut preferred: HashMap<&'static str, &'static str> = HashMap::new();
+        // 短い名前、大文字の少ない名前を選ぶ: &amp; (&AMP; ではなく)、&nbsp;
+        // (&NonBreakingSpace; ではなく)
+        let key = |name: &str| {
+            let upper = name.chars().filter(|c| c.is_ascii_uppercase()).count();
+            return (name.len(), upper, name.to_string());
+        };
+        for e in ENTITIES.iter().filter(|e| e.entity.ends_with(';')) {
+            let better = match preferred.get(e.characters) {
+                Some(name) => key(e.entity) < key(name),
+                None => true,
+            };
+            if better {
+                preferred.insert(e.characters, e.entity);
+            }
+        }
+        return preferred;
+    });
+    return preferred.get(decoded).copied();
+}
+
+/// Numeric references to C1 controls mean the Windows-1252 character instead.
+fn replace_c1(code: u32) -> u32 {
+    return match code {
+        0x80 => 0x20AC,
+        0x82 => 0x201A,
+        0x83 => 0x0192,
+        0x84 => 0x201E,
+        0x85 => 0x2026,
+        0x86 => 0x2020,
+        0x87 => 0x2021,
+        0x88 => 0x02C6,
+        0x89 => 0x2030,
+        0x8A => 0x0160,
+        0x8B => 0x2039,
+        0x8C => 0x0152,
+        0x8E => 0x017D,
+        0x91 => 0x2018,
+        0x92 => 0x2019,
+        0x93 => 0x201C,
+        0x94 => 0x201D,
+        0x95 => 0x2022,
+        0x96 => 0x2013,
+        0x97 => 0x2014,
+        0x98 => 0x02DC,
+        0x99 => 0x2122,
+        0x9A => 0x0161,
+        0x9B => 0x203A,
+        0x9C => 0x0153,
+        0x9E => 0x017E,
+        0x9F => 0x0178,
+        _ => code,
+    };
+}
+
+fn read_numeric(input: &str) -> Option<CharRef> {
+    let body = &input[2..];
+    let hex = body.starts_with(['x', 'X']);
+    let digits_at = if hex { 3 } else { 2 };
+    let radix = if hex { 16 } else { 10 };
+
+    let digits: &str = &input[digits_at..];
+    let n = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    // "&#" や "&#x" だけなら参照ではない
+    if n == 0 {
+        return None;
+    }
+
+    let mut code: u32 = 0;
+    for c in digits[..n].chars() {
+        code = code
+            .saturating_mul(radix)
+            .saturating_add(c.to_digit(radix).unwrap());
+    }
+    let decoded = match code {
+        0 => '\u{FFFD}',
+        code => char::from_u32(replace_c1(code)).unwrap_or('\u{FFFD}'),
+    };
+
+    let mut len = digits_at + n;
+    if input[len..].starts_with(';') {
+        len += 1;
+    }
+    return Some(CharRef {
+        len,
+        decoded: decoded.to_string(),
+    });
+}
+
+fn read_named(input: &str, in_attribute: bool) -> Option<CharRef> {
+    let body = &input[1..];
+    let word_len = body
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(body.len());
+    let word = &body[..word_len];
+
+    if body[word_len..].starts_with(';') {
+        if let Some(decoded) = names().get(&body[..word_len + 1]) {
+            return Some(CharRef {
+                len: word_len + 2,
+                decoded: decoded.to_string(),
+            });
+        }
+    }
+
+    // ";" のない古い書き方は一番長く一致する名前を使う: "&notit;" は "&not" + "it;"
+    for n in (1..=word_len).rev() {
+        let decoded = match names().get(&word[..n]) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+        // 属性値の中の "?a=1&copy=2" は参照ではない
+        let next = body[n..].chars().next();
+        if in_attribute && matches!(next, Some(c) if c == '=' || c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        return Some(CharRef {
+            len: n + 1,
+            decoded: decoded.to_string(),
+        });
+    }
+    return None;
+}
+
+/// Reads the character reference at the start of `input`, which begins with
+/// `&`. Returns `None` when the `&` doesn't start one and is just a character.
+pub fn read(input: &str, in_attribute: bool) -> Option<CharRef> {
+    if input.starts_with("&#") {
+        return read_numeric(input);
+    }
+    return read_named(input, in_attribute);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tokenize::charref::{name_of, read, CharRef};
+
+    fn decode(input: &str) -> Option<(usize, String)> {
+        return read(input, false).map(|r: CharRef| (r.len, r.decoded));
+    }
+
+    #[test]
+    fn named_references() {
+        assert_eq!(decode("&amp;x"), Some((5, "&".to_string())));
+        assert_eq!(decode("&nbsp"), Some((5, "\u{A0}".to_string())));
+        assert_eq!(decode("&notin;"), Some((7, "∉".to_string())));
+        assert_eq!(decode("&notit;"), Some((4, "¬".to_string())));
+        assert_eq!(
+            decode("&NotEqualTilde;"),
+            Some((15, "≂\u{338}".to_string()))
+        );
+        assert_eq!(decode("&unknown;"), None);
+        assert_eq!(decode("& b"), None);
+    }
+
+    #[test]
+    fn numeric_references() {
+        assert_eq!(decode("&#x3042;"), Some((8, "あ".to_string())));
+        assert_eq!(decode("&#12354;"), Some((8, "あ".to_string())));
+        assert_eq!(decode("&#X41"), Some((5, "A".to_string())));
+        assert_eq!(decode("&#128;"), Some((6, "€".to_string())));
+        assert_eq!(decode("&#0;"), Some((4, "\u{FFFD}".to_string())));
+        assert_eq!(decode("&#xD800;"), Some((8, "\u{FFFD}".to_string())));
+        assert_eq!(decode("&#99999999999;"), Some((14, "\u{FFFD}".to_string())));
+        assert_eq!(decode("&#;"), None);
+        assert_eq!(decode("&#xg;"), None);
+    }
+
+    #[test]
+    fn legacy_names_in_attributes() {
+        assert_eq!(read("&copy=2", true), None);
+        assert_eq!(read("&copyx", true), None);
+        assert_eq!(read("&copy;=2", true).unwrap().decoded, "©");
+        assert_eq!(read("&copy 2", true).unwrap().decoded, "©");
+    }
+
+    #[test]
+    fn preferred_names() {
+        assert_eq!(name_of("&"), Some("&amp;"));
+        assert_eq!(name_of("\u{A0}"), Some("&nbsp;"));
+        assert_eq!(name_of("<"), Some("&lt;"));
+        assert_eq!(name_of("あ"), None);
+    }
+}
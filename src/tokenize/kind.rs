@@ -1,37 +1,12 @@
-use crate::tokenize::kind::TokenKind::{
-    Amp, Assign, Exclamation, Hyphen, Illegal, Slash, TagBegin, TagEnd,
-};
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
-    Illegal,
     Eof,
-    Whitespace,
-
-    TagBegin,    // <
-    TagEnd,      // >
-    Exclamation, // !
-    Assign,      // =
-    Hyphen,      // -
-    Slash,       // /
-    Amp,         // &
 
-    String,
-    Integer,
-    Decimal,
+    Doctype,  // <!DOCTYPE html>
+    StartTag, // <x a="b">
+    EndTag,   // </x>
+    Comment,  // <!-- x -->
 
     Text,
-}
-
-pub fn symbol_kind(symbol: &str) -> TokenKind {
-    match symbol {
-        "<" => TagBegin,
-        ">" => TagEnd,
-        "!" => Exclamation,
-        "=" => Assign,
-        "-" => Hyphen,
-        "/" => Slash,
-        "&" => Amp,
-        _ => Illegal,
-    }
+    CharRef, // &amp;
 }
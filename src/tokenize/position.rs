@@ -1,16 +1,84 @@
+/// A point in the source. Columns are counted from 0 in chars and in UTF-16
+/// code units (what LSP clients use); offsets from the start of the source in
+/// chars and in bytes (for slicing the `&str`).
 #[derive(Debug, Clone, PartialEq)]
 pub struct Position {
+    /// Line number, starting at 1.
     pub line_no: u32,
     pub at_line: u32,
+    pub at_line_utf16: u32,
     pub at_whole: u32,
+    pub at_byte: usize,
 }
 
 impl Position {
-    pub fn new(line_no: u32, at_line: u32, at_whole: u32) -> Position {
+    pub fn new(
+        line_no: u32,
+        at_line: u32,
+        at_line_utf16: u32,
+        at_whole: u32,
+        at_byte: usize,
+    ) -> Position {
         return Position {
             line_no,
             at_line,
+            at_line_utf16,
             at_whole,
+            at_byte,
         };
     }
+
+    /// Moves past `c`, which must be the character at this position.
+    pub fn advance(&mut self, c: char) {
+        if c == '\n' {
+            self.line_no += 1;
+            self.at_line = 0;
+            self.at_line_utf16 = 0;
+        } else {
+            self.at_line += 1;
+            self.at_line_utf16 += c.len_utf16() as u32;
+        }
+        self.at_whole += 1;
+        self.at_byte += c.len_utf8();
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        return Position::new(1, 0, 0, 0, 0);
+    }
+}
+
+/// Source range from `start` (inclusive) to `end` (exclusive).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        return Span { start, end };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tokenize::position::Position;
+
+    #[test]
+    fn advance_counts_bytes_chars_and_utf16() {
+        let mut pos = Position::default();
+        for c in "あ😀\nb".chars() {
+            pos.advance(c);
+        }
+        assert_eq!(pos, Position::new(2, 1, 1, 4, 9));
+
+        let mut pos = Position::default();
+        for c in "あ😀".chars() {
+            pos.advance(c);
+        }
+        // あ は UTF-16 で 1、😀 はサロゲートペアで 2
+        assert_eq!(pos, Position::new(1, 2, 3, 2, 7));
+    }
 }
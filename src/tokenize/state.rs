@@ -0,0 +1,56 @@
+/// The tokenization states of the HTML Living Standard (section 13.2.5) that
+/// the tokenizer goes through.
+///
+/// The states that only exist to report parse errors (the comment less-than
+/// sign states, the script data escape states, ...) are folded into their
+/// neighbours, and the raw text states find their end tag in one step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Data,
+    RcData,  // <textarea>, <title>
+    RawText, // <style> など
+    ScriptData,
+    PlainText,
+
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    SelfClosingStartTag,
+
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueDoubleQuoted,
+    AttributeValueSingleQuoted,
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+
+    MarkupDeclarationOpen,
+    BogusComment,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+}
+
+impl State {
+    /// The state to switch to after the start tag `name`, for the elements
+    /// whose contents are not markup.
+    pub fn after_start_tag(name: &str) -> State {
+        return match name.to_ascii_lowercase().as_str() {
+            "textarea" | "title" => State::RcData,
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => State::RawText,
+            "script" => State::ScriptData,
+            "plaintext" => State::PlainText,
+            _ => State::Data,
+        };
+    }
+}
@@ -1,25 +1,74 @@
 use crate::tokenize::kind::TokenKind;
-use crate::tokenize::position::Position;
+use crate::tokenize::position::Span;
+
+/// One attribute of a start tag. The name and value are kept as written
+/// (the value without its quotes); `None` means the attribute had no `=` at
+/// all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: Option<String>,
+    pub name_span: Span,
+    // 引用符も含む
+    pub value_span: Option<Span>,
+}
+
+impl Attribute {
+    pub fn new(name: &str, value: Option<&str>) -> Attribute {
+        return Attribute {
+            name: name.to_string(),
+            value: value.map(|v| v.to_string()),
+            name_span: Span::default(),
+            value_span: None,
+        };
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub(crate) kind: TokenKind,
-    pub(crate) pos: Position,
+    pub(crate) span: Span,
+    // タグ名、コメントの中身、doctype、テキスト、書かれたままの文字参照
     pub(crate) imm_s: String,
-    pub(crate) imm_f: f64,
-    pub(crate) imm_i: i64,
-    pub(crate) next: Option<Box<Token>>,
+    // 文字参照の表す文字
+    pub(crate) decoded: String,
+    pub(crate) attrs: Vec<Attribute>,
+    pub(crate) self_closing: bool,
+    // 終了タグとコメントは書かれたままも持っておく。閉じるもののない終了タグや
+    // <?xml ?> のようなコメントはこのまま書き戻す
+    pub(crate) raw: String,
+    // <!-- のコメントが閉じられないまま入力が終わった、または開始タグが
+    // 閉じられないまま次のタグに来た: <span\n</div>
+    pub(crate) unclosed: bool,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, pos: Position, imm_s: String, imm_f: f64, imm_i: i64) -> Token {
+    pub fn new(kind: TokenKind, span: Span, imm_s: String) -> Token {
         return Token {
             kind,
-            pos,
+            span,
             imm_s,
-            imm_f,
-            imm_i,
-            next: None,
+            decoded: "".to_string(),
+            attrs: vec![],
+            self_closing: false,
+            raw: "".to_string(),
+            unclosed: false,
         };
     }
+
+    /// Span of the name of a start or end tag, which always directly follows
+    /// `<` or `</`.
+    pub(crate) fn name_span(&self) -> Span {
+        let mut start = self.span.start.clone();
+        start.advance('<');
+        if self.kind == TokenKind::EndTag {
+            start.advance('/');
+        }
+        // 名前は書かれたまま持っているので、そのまま数えられる
+        let mut end = start.clone();
+        for c in self.imm_s.chars() {
+            end.advance(c);
+        }
+        return Span::new(start, end);
+    }
 }
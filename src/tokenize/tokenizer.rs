@@ -1,324 +1,689 @@
-use crate::tokenize::kind::{symbol_kind, TokenKind};
-use crate::tokenize::position::Position;
-use crate::tokenize::token::Token;
-use std::str::Chars;
-
-pub struct Tokenizer {
-    target: String,
+use crate::tokenize::charref;
+use crate::tokenize::kind::TokenKind;
+use crate::tokenize::position::{Position, Span};
+use crate::tokenize::state::State;
+use crate::tokenize::token::{Attribute, Token};
+use std::collections::VecDeque;
+
+/// Splits HTML into doctype, tag, comment and text tokens, following the
+/// tokenization state machine of the HTML Living Standard.
+///
+/// Unlike a browser the tokenizer never drops source text: text is kept as
+/// written, character references become `CharRef` tokens that hold both the
+/// source and the decoded characters, and a tag cut off by the end of the
+/// input is handed out as text.
+pub struct Tokenizer<'s> {
+    target: &'s str,
+    // 読んでいる位置。at_byte がカーソル
     pos: Position,
+    state: State,
+    // まだ出していないテキストの読み始め
+    text_start: Position,
+    // 組み立て中のトークンとその読み始め
+    start: Position,
+    current: Token,
+    attr: Option<Attribute>,
+    // 最後に出した開始タグの名前。raw text の終わりを探すのに使う
+    last_start_tag: String,
+    pending: VecDeque<Token>,
+    // Eof を返し終わった
+    done: bool,
+}
+
+fn is_white(c: char) -> bool {
+    return matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ');
+}
+
+/// Whether `s` starts with the end tag `name`.
+fn is_end_tag(s: &str, name: &str) -> bool {
+    let after = match s.strip_prefix("</") {
+        Some(after) => after,
+        None => return false,
+    };
+    // 途中で入力が終わったり、文字の途中で切れたりするなら違う
+    let head = match after.get(..name.len()) {
+        Some(head) => head,
+        None => return false,
+    };
+    return head.eq_ignore_ascii_case(name)
+        && match after[name.len()..].chars().next() {
+            Some(c) => is_white(c) || c == '/' || c == '>',
+            None => false,
+        };
 }
 
-impl Tokenizer {
-    pub fn new(target: &str) -> Tokenizer {
+impl<'s> Tokenizer<'s> {
+    pub fn new(target: &'s str) -> Tokenizer<'s> {
         return Tokenizer {
-            target: target.to_string(),
-            pos: Position::new(1, 0, 0),
+            target,
+            pos: Position::default(),
+            state: State::Data,
+            text_start: Position::default(),
+            start: Position::default(),
+            current: Token::new(TokenKind::Text, Span::default(), "".to_string()),
+            attr: None,
+            last_start_tag: "".to_string(),
+            pending: VecDeque::new(),
+            done: false,
         };
     }
 
-    fn is_eof(&self) -> bool {
-        return self.pos.at_whole >= self.target.len() as u32;
+    fn rest(&self) -> &'s str {
+        return &self.target[self.pos.at_byte..];
     }
 
-    fn move_horizon(&mut self, n: u32) {
-        self.pos.at_line += n;
-        self.pos.at_whole += n;
+    fn peek(&self) -> Option<char> {
+        return self.rest().chars().next();
     }
 
-    fn move_line(&mut self, n: u32) {
-        self.pos.at_line = 0;
-        self.pos.at_whole += n;
-        self.pos.line_no += n;
+    /// Moves `n` characters ahead, keeping track of lines and columns.
+    fn advance(&mut self, n: usize) {
+        for _ in 0..n {
+            match self.peek() {
+                Some(c) => self.pos.advance(c),
+                None => break,
+            }
+        }
     }
 
-    fn current(&self) -> char {
-        return self.target.chars().nth(self.pos.at_whole as usize).unwrap();
+    /// Span from `start` up to the cursor.
+    fn span_from(&self, start: Position) -> Span {
+        return Span::new(start, self.pos.clone());
     }
 
-    fn peek(&self, n: u32) -> char {
-        return self
-            .target
-            .chars()
-            .nth((self.pos.at_whole + n) as usize)
-            .unwrap();
+    /// Starts building a token of `kind` that began at `start`.
+    fn begin(&mut self, kind: TokenKind, start: Position) {
+        self.start = start;
+        self.current = Token::new(kind, Span::default(), "".to_string());
+        self.attr = None;
     }
 
-    fn start_with(&self, word: String) -> bool {
-        let chars: Chars = word.chars();
-        for (i, c) in chars.enumerate() {
-            if self.peek(i as u32) != c {
-                return false;
-            }
+    /// Hands out the text read so far, up to `end`.
+    fn flush_text(&mut self, end: &Position) {
+        if self.text_start.at_byte < end.at_byte {
+            let text = self.target[self.text_start.at_byte..end.at_byte].to_string();
+            let span = Span::new(self.text_start.clone(), end.clone());
+            self.pending
+                .push_back(Token::new(TokenKind::Text, span, text));
         }
-        return true;
     }
 
-    fn is_white(&self) -> bool {
-        return self.current() == '\n' || self.current() == '\t' || self.current() == ' ';
-    }
+    /// Hands out the token being built, which ends at the cursor.
+    fn emit(&mut self) {
+        let start = self.start.clone();
+        self.flush_text(&start);
+        self.end_attribute();
 
-    fn is_number(&self) -> bool {
-        return self.current() == '0'
-            || self.current() == '1'
-            || self.current() == '2'
-            || self.current() == '3'
-            || self.current() == '4'
-            || self.current() == '5'
-            || self.current() == '6'
-            || self.current() == '7'
-            || self.current() == '8'
-            || self.current() == '9';
-    }
+        let mut tok = std::mem::replace(
+            &mut self.current,
+            Token::new(TokenKind::Text, Span::default(), "".to_string()),
+        );
+        tok.span = self.span_from(start);
+        if tok.kind == TokenKind::Doctype {
+            tok.imm_s = tok.imm_s.trim_end().to_string();
+        }
+        if matches!(tok.kind, TokenKind::EndTag | TokenKind::Comment) || tok.unclosed {
+            tok.raw = self.target[tok.span.start.at_byte..tok.span.end.at_byte].to_string();
+        }
 
-    fn is_symbol(&self) -> bool {
-        let symbols: Vec<&str> = vec!["<", ">", "!", "=", "-", "/", "&"];
-        for s in symbols {
-            if self.start_with(s.to_string()) {
-                return true;
-            }
+        self.state = State::Data;
+        // <script src=x /> は中身のない要素として扱うので、生テキストに入らない
+        if tok.kind == TokenKind::StartTag && !tok.self_closing && !tok.unclosed {
+            self.last_start_tag = tok.imm_s.clone();
+            self.state = State::after_start_tag(&tok.imm_s);
         }
+        self.pending.push_back(tok);
+        self.text_start = self.pos.clone();
+    }
 
-        return false;
+    /// The input ended inside a tag. Browsers drop such a tag; here it is
+    /// left in the text so that nothing is lost.
+    fn eof_in_tag(&mut self) {
+        self.attr = None;
+        self.state = State::Data;
     }
 
-    fn is_alphanum_(&self) -> bool {
-        return self.current().is_alphanumeric() || self.current() == '_';
+    /// The input ended inside a `<!--` comment. The comment is handed out
+    /// marked as unclosed, so that the parser can report it.
+    fn eof_in_comment(&mut self) {
+        self.current.unclosed = true;
+        self.emit();
     }
 
-    fn consume_string(&mut self, is_single: bool) -> String {
-        let mut s: String = "".to_string();
+    /// A start tag ran into the next tag, as in `<span\n</div>` while it is
+    /// still being typed. The tag ends before the `<`, marked as unclosed so
+    /// that the parser keeps it as written, and the `<` is read again.
+    fn tag_cut_off(&mut self) {
+        self.current.unclosed = true;
+        self.emit();
+    }
 
-        // consume start single/double quotation
-        self.move_horizon(1);
+    fn begin_attribute(&mut self) {
+        self.end_attribute();
+        self.attr = Some(Attribute {
+            name: "".to_string(),
+            value: None,
+            name_span: Span::new(self.pos.clone(), self.pos.clone()),
+            value_span: None,
+        });
+    }
 
-        while !self.is_eof() {
-            let cur = self.current();
-            if cur == '\'' && is_single {
-                break;
-            }
-            if cur == '"' && !is_single {
-                break;
-            }
-            s += &*cur.to_string();
-            self.move_horizon(1);
+    fn end_attribute(&mut self) {
+        if let Some(attr) = self.attr.take() {
+            self.current.attrs.push(attr);
         }
-
-        // consume end single/double quotation
-        self.move_horizon(1);
-
-        return s;
     }
 
-    fn consume_numeric(&mut self) -> (f64, bool) {
-        let mut s: String = "".to_string();
-        let mut include_dot: bool = false;
-
-        while !self.is_eof() {
-            if self.is_number() {
-                s += &*self.current().to_string()
-            } else if self.current() == '.' {
-                s += &*self.current().to_string();
-                include_dot = true;
-            } else {
-                break;
-            }
-            self.move_horizon(1);
-        }
+    fn attribute(&mut self) -> &mut Attribute {
+        return self.attr.as_mut().unwrap();
+    }
 
-        return (s.parse().unwrap(), include_dot);
+    /// Attribute names keep their case, unlike in browsers: templates use
+    /// `[ngModel]`, `*ngIf` or `:someProp`, and they mean something else in
+    /// lowercase.
+    fn push_name(&mut self, c: char) {
+        self.attribute().name.push(c);
+        self.attribute().name_span.end.advance(c);
     }
 
-    fn consume_white(&mut self) -> String {
-        let mut s: String = "".to_string();
+    fn begin_value(&mut self) {
+        let start = self.pos.clone();
+        let attr = self.attribute();
+        attr.value = Some("".to_string());
+        attr.value_span = Some(Span::new(start.clone(), start));
+    }
 
-        while !self.is_eof() {
-            if self.is_white() && self.current() != '\n' {
-                s += &*self.current().to_string();
-                self.move_horizon(1);
-            } else if self.current() == '\n' {
-                s += &*self.current().to_string();
-                self.move_line(1);
-            } else {
-                break;
-            }
+    fn end_value(&mut self) {
+        let end = self.pos.clone();
+        if let Some(span) = self.attribute().value_span.as_mut() {
+            span.end = end;
         }
-
-        return s;
     }
 
-    fn consume_symbol(&mut self) -> String {
-        let s: String = self.current().to_string();
-        self.move_horizon(1);
-        return s;
+    fn push_value(&mut self, c: char) {
+        self.attribute().value.as_mut().unwrap().push(c);
     }
 
-    fn consume_text(&mut self) -> String {
-        let mut s: String = "".to_string();
+    /// Skips the contents of a raw text element up to its end tag (or the end
+    /// of the input); they become one text token.
+    fn raw_text(&mut self) {
+        let name = self.last_start_tag.clone();
+        let rest = self.rest();
+
+        let mut at = 0;
+        let end = loop {
+            let found = match rest[at..].find("</") {
+                Some(i) => at + i,
+                None => break rest.len(),
+            };
+            if is_end_tag(&rest[found..], &name) {
+                break found;
+            }
+            at = found + 2;
+        };
 
-        if !self.is_alphanum_() {
-            s = self.current().to_string();
-            self.move_horizon(1);
-            return s;
-        }
+        let n = rest[..end].chars().count();
+        self.advance(n);
+        self.state = State::Data;
+    }
 
-        while !self.is_eof() {
-            if self.is_alphanum_() {
-                s += &*self.current().to_string();
-                self.move_horizon(1);
-            } else {
-                break;
+    /// Reads the character reference at the cursor, if the `&` there starts
+    /// one.
+    fn char_ref(&mut self) {
+        let found = match charref::read(self.rest(), false) {
+            Some(found) => found,
+            None => {
+                self.advance(1);
+                return;
             }
-        }
+        };
+        let start = self.pos.clone();
+        self.flush_text(&start);
 
-        return s;
-    }
-
-    fn link_white_token<'a>(
-        &mut self,
-        cur: &'a mut Token,
-        pos: Position,
-        ws: String,
-    ) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(TokenKind::Whitespace, pos.clone(), ws, 0 as f64, 0);
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    fn link_symbol_token<'a>(
-        &mut self,
-        cur: &'a mut Token,
-        pos: Position,
-        symbol: String,
-    ) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(
-            symbol_kind(symbol.as_str()),
-            pos.clone(),
-            symbol,
-            0 as f64,
-            0,
-        );
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    fn link_decimal_token<'a>(
-        &mut self,
-        cur: &'a mut Token,
-        pos: Position,
-        f: f64,
-    ) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(TokenKind::Decimal, pos.clone(), "".to_string(), f, 0);
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    fn link_integer_token<'a>(
-        &mut self,
-        cur: &'a mut Token,
-        pos: Position,
-        i: i64,
-    ) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(TokenKind::Integer, pos.clone(), "".to_string(), 0 as f64, i);
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    fn link_string_token<'a>(
-        &mut self,
-        cur: &'a mut Token,
-        pos: Position,
-        s: String,
-    ) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(TokenKind::String, pos.clone(), s, 0 as f64, 0);
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    fn link_text_token<'a>(
-        &mut self,
-        cur: &'a mut Token,
-        pos: Position,
-        s: String,
-    ) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(TokenKind::Text, pos.clone(), s, 0 as f64, 0);
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    fn link_eof_token<'a>(&mut self, cur: &'a mut Token, pos: Position) -> &'a mut Box<Token> {
-        let tok: Token = Token::new(TokenKind::Eof, pos.clone(), "".to_string(), 0 as f64, 0);
-        cur.next = Some(Box::new(tok));
-        return cur.next.as_mut().unwrap();
-    }
-
-    pub fn tokenize(&mut self) -> Box<Token> {
-        let mut head = Token::new(
-            TokenKind::Illegal,
-            self.pos.clone(),
-            "".to_string(),
-            0 as f64,
-            0,
-        );
+        let raw = &self.rest()[..found.len];
+        let mut tok = Token::new(TokenKind::CharRef, Span::default(), raw.to_string());
+        tok.decoded = found.decoded;
+        self.advance(raw.chars().count());
+        tok.span = self.span_from(start);
 
-        let mut cur: &mut Token = &mut head;
-        while !self.is_eof() {
-            // whitespace
-            if self.is_white() {
-                let ws: String = self.consume_white();
-                cur = self.link_white_token(cur, self.pos.clone(), ws);
-                continue;
-            }
+        self.pending.push_back(tok);
+        self.text_start = self.pos.clone();
+    }
 
-            // symbol
-            if self.is_symbol() {
-                let sym: String = self.consume_symbol();
-                cur = self.link_symbol_token(cur, self.pos.clone(), sym);
-                continue;
-            }
+    fn finish(&mut self) {
+        let end = self.pos.clone();
+        self.flush_text(&end);
+        let eof = Token::new(TokenKind::Eof, self.span_from(end), "".to_string());
+        self.pending.push_back(eof);
+        self.done = true;
+    }
 
-            // string
-            if self.current() == '\'' {
-                let s: String = self.consume_string(true);
-                cur = self.link_string_token(cur, self.pos.clone(), s);
-                continue;
-            }
-            if self.current() == '"' {
-                let s: String = self.consume_string(false);
-                cur = self.link_string_token(cur, self.pos.clone(), s);
-                continue;
+    /// Runs the state machine for one character.
+    fn step(&mut self) {
+        let c = self.peek();
+        match self.state {
+            State::Data => match c {
+                Some('<') => {
+                    self.start = self.pos.clone();
+                    self.advance(1);
+                    self.state = State::TagOpen;
+                }
+                Some('&') => self.char_ref(),
+                Some(_) => self.advance(1),
+                None => self.finish(),
+            },
+            State::RcData => match c {
+                Some('<') if is_end_tag(self.rest(), &self.last_start_tag) => {
+                    self.state = State::Data
+                }
+                Some('&') => self.char_ref(),
+                Some(_) => self.advance(1),
+                None => self.state = State::Data,
+            },
+            State::RawText | State::ScriptData => self.raw_text(),
+            State::PlainText => {
+                let n = self.rest().chars().count();
+                self.advance(n);
+                self.finish();
             }
 
-            // integer/decimal
-            if self.is_number() {
-                let f_b: (f64, bool) = self.consume_numeric();
-                if f_b.1 {
-                    // include dot
-                    // f.0 is decimal
-                    cur = self.link_decimal_token(cur, self.pos.clone(), f_b.0);
+            State::TagOpen => match c {
+                Some('!') => {
+                    self.advance(1);
+                    self.state = State::MarkupDeclarationOpen;
+                }
+                Some('/') => {
+                    self.advance(1);
+                    self.state = State::EndTagOpen;
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.begin(TokenKind::StartTag, self.start.clone());
+                    self.state = State::TagName;
+                }
+                Some('?') => {
+                    self.begin(TokenKind::Comment, self.start.clone());
+                    self.state = State::BogusComment;
+                }
+                // "<" はただの文字
+                _ => self.state = State::Data,
+            },
+            State::EndTagOpen => match c {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.begin(TokenKind::EndTag, self.start.clone());
+                    self.state = State::TagName;
+                }
+                // "</>" は捨てずにテキストとして残す
+                Some('>') => {
+                    self.advance(1);
+                    self.state = State::Data;
+                }
+                None => self.state = State::Data,
+                Some(_) => {
+                    self.begin(TokenKind::Comment, self.start.clone());
+                    self.state = State::BogusComment;
+                }
+            },
+            State::TagName => match c {
+                Some(c) if is_white(c) => {
+                    self.advance(1);
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('/') => {
+                    self.advance(1);
+                    self.state = State::SelfClosingStartTag;
+                }
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some('<') if self.current.kind == TokenKind::StartTag => self.tag_cut_off(),
+                Some(c) => {
+                    self.current.imm_s.push(c);
+                    self.advance(1);
+                }
+                None => self.eof_in_tag(),
+            },
+            State::SelfClosingStartTag => match c {
+                Some('>') => {
+                    self.current.self_closing = true;
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(_) => self.state = State::BeforeAttributeName,
+                None => self.eof_in_tag(),
+            },
+
+            State::BeforeAttributeName => match c {
+                Some(c) if is_white(c) => self.advance(1),
+                Some('/') | Some('>') | None => self.state = State::AfterAttributeName,
+                Some('<') if self.current.kind == TokenKind::StartTag => self.tag_cut_off(),
+                Some('=') => {
+                    // "=" で始まる属性名
+                    self.begin_attribute();
+                    self.push_name('=');
+                    self.advance(1);
+                    self.state = State::AttributeName;
+                }
+                Some(_) => {
+                    self.begin_attribute();
+                    self.state = State::AttributeName;
+                }
+            },
+            State::AttributeName => match c {
+                Some(c) if is_white(c) || c == '/' || c == '>' => {
+                    self.state = State::AfterAttributeName
+                }
+                None => self.state = State::AfterAttributeName,
+                Some('<') if self.current.kind == TokenKind::StartTag => self.tag_cut_off(),
+                Some('=') => {
+                    self.advance(1);
+                    self.state = State::BeforeAttributeValue;
+                }
+                Some(c) => {
+                    self.push_name(c);
+                    self.advance(1);
+                }
+            },
+            State::AfterAttributeName => match c {
+                Some(c) if is_white(c) => self.advance(1),
+                Some('/') => {
+                    self.end_attribute();
+                    self.advance(1);
+                    self.state = State::SelfClosingStartTag;
+                }
+                Some('=') => {
+                    self.advance(1);
+                    self.state = State::BeforeAttributeValue;
+                }
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some('<') if self.current.kind == TokenKind::StartTag => self.tag_cut_off(),
+                Some(_) => {
+                    self.begin_attribute();
+                    self.state = State::AttributeName;
+                }
+                None => self.eof_in_tag(),
+            },
+            State::BeforeAttributeValue => match c {
+                Some(c) if is_white(c) => self.advance(1),
+                Some('"') => {
+                    self.begin_value();
+                    self.advance(1);
+                    self.state = State::AttributeValueDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.begin_value();
+                    self.advance(1);
+                    self.state = State::AttributeValueSingleQuoted;
+                }
+                Some('>') => {
+                    // 値がない: 空文字列として扱う
+                    self.begin_value();
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(_) => {
+                    self.begin_value();
+                    self.state = State::AttributeValueUnquoted;
+                }
+                None => self.eof_in_tag(),
+            },
+            State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted => {
+                let quote = match self.state {
+                    State::AttributeValueDoubleQuoted => '"',
+                    _ => '\'',
+                };
+                match c {
+                    Some(c) if c == quote => {
+                        self.advance(1);
+                        self.end_value();
+                        self.state = State::AfterAttributeValueQuoted;
+                    }
+                    Some(c) => {
+                        self.push_value(c);
+                        self.advance(1);
+                    }
+                    None => self.eof_in_tag(),
+                }
+            }
+            State::AttributeValueUnquoted => match c {
+                Some(c) if is_white(c) => {
+                    self.end_value();
+                    self.advance(1);
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('>') => {
+                    self.end_value();
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(c) => {
+                    self.push_value(c);
+                    self.advance(1);
+                }
+                None => self.eof_in_tag(),
+            },
+            State::AfterAttributeValueQuoted => match c {
+                Some(c) if is_white(c) => {
+                    self.advance(1);
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('/') => {
+                    self.advance(1);
+                    self.state = State::SelfClosingStartTag;
+                }
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(_) => self.state = State::BeforeAttributeName,
+                None => self.eof_in_tag(),
+            },
+
+            State::MarkupDeclarationOpen => {
+                let rest = self.rest();
+                if rest.starts_with("--") {
+                    self.advance(2);
+                    self.begin(TokenKind::Comment, self.start.clone());
+                    self.state = State::CommentStart;
+                } else if rest.len() >= 7 && rest.as_bytes()[..7].eq_ignore_ascii_case(b"doctype") {
+                    self.advance(7);
+                    self.begin(TokenKind::Doctype, self.start.clone());
+                    self.state = State::Doctype;
                 } else {
-                    // not include dot
-                    // f.0 is integer
-                    cur = self.link_integer_token(cur, self.pos.clone(), f_b.0 as i64);
+                    // "<![CDATA[" も HTML の中ではただのコメント
+                    self.begin(TokenKind::Comment, self.start.clone());
+                    self.state = State::BogusComment;
                 }
-                continue;
             }
+            State::BogusComment => match c {
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(c) => {
+                    self.current.imm_s.push(c);
+                    self.advance(1);
+                }
+                None => self.emit(),
+            },
+            State::CommentStart => match c {
+                Some('-') => {
+                    self.advance(1);
+                    self.state = State::CommentStartDash;
+                }
+                Some('>') => {
+                    // "<!-->"
+                    self.advance(1);
+                    self.emit();
+                }
+                _ => self.state = State::Comment,
+            },
+            State::CommentStartDash => match c {
+                Some('-') => {
+                    self.advance(1);
+                    self.state = State::CommentEnd;
+                }
+                Some('>') => {
+                    // "<!--->"
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(_) => {
+                    self.current.imm_s.push('-');
+                    self.state = State::Comment;
+                }
+                None => self.eof_in_comment(),
+            },
+            State::Comment => match c {
+                Some('-') => {
+                    self.advance(1);
+                    self.state = State::CommentEndDash;
+                }
+                Some(c) => {
+                    self.current.imm_s.push(c);
+                    self.advance(1);
+                }
+                None => self.eof_in_comment(),
+            },
+            State::CommentEndDash => match c {
+                Some('-') => {
+                    self.advance(1);
+                    self.state = State::CommentEnd;
+                }
+                Some(_) => {
+                    self.current.imm_s.push('-');
+                    self.state = State::Comment;
+                }
+                None => self.eof_in_comment(),
+            },
+            State::CommentEnd => match c {
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some('!') => {
+                    self.advance(1);
+                    self.state = State::CommentEndBang;
+                }
+                Some('-') => {
+                    self.current.imm_s.push('-');
+                    self.advance(1);
+                }
+                Some(_) => {
+                    self.current.imm_s.push_str("--");
+                    self.state = State::Comment;
+                }
+                None => self.eof_in_comment(),
+            },
+            State::CommentEndBang => match c {
+                Some('-') => {
+                    self.current.imm_s.push_str("--!");
+                    self.advance(1);
+                    self.state = State::CommentEndDash;
+                }
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(_) => {
+                    self.current.imm_s.push_str("--!");
+                    self.state = State::Comment;
+                }
+                None => self.eof_in_comment(),
+            },
 
-            // 直書きの文字
-            let text = self.consume_text();
-            cur = self.link_text_token(cur, self.pos.clone(), text);
-            continue;
+            State::Doctype => match c {
+                Some(c) if is_white(c) => {
+                    self.advance(1);
+                    self.state = State::BeforeDoctypeName;
+                }
+                None => self.emit(),
+                Some(_) => self.state = State::BeforeDoctypeName,
+            },
+            State::BeforeDoctypeName => match c {
+                Some(c) if is_white(c) => self.advance(1),
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(c) => {
+                    self.current.imm_s.push(c.to_ascii_lowercase());
+                    self.advance(1);
+                    self.state = State::DoctypeName;
+                }
+                None => self.emit(),
+            },
+            State::DoctypeName => match c {
+                Some(c) if is_white(c) => {
+                    self.current.imm_s.push(' ');
+                    self.advance(1);
+                    self.state = State::AfterDoctypeName;
+                }
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(c) => {
+                    self.current.imm_s.push(c.to_ascii_lowercase());
+                    self.advance(1);
+                }
+                None => self.emit(),
+            },
+            // PUBLIC / SYSTEM 識別子は書かれたまま残す
+            State::AfterDoctypeName => match c {
+                Some(c) if is_white(c) => {
+                    if !self.current.imm_s.ends_with(' ') {
+                        self.current.imm_s.push(' ');
+                    }
+                    self.advance(1);
+                }
+                Some('>') => {
+                    self.advance(1);
+                    self.emit();
+                }
+                Some(c) => {
+                    self.current.imm_s.push(c);
+                    self.advance(1);
+                }
+                None => self.emit(),
+            },
         }
+    }
 
-        cur = self.link_eof_token(cur, self.pos.clone());
+    /// Reads the whole source into a list of tokens, ending with `Eof`.
+    pub fn tokenize(&mut self) -> Vec<Token> {
+        return self.collect();
+    }
+}
+
+/// Yields one token at a time; the last one is always `Eof`.
+impl Iterator for Tokenizer<'_> {
+    type Item = Token;
 
-        return head.next.unwrap();
+    fn next(&mut self) -> Option<Token> {
+        while self.pending.is_empty() && !self.done {
+            self.step();
+        }
+        return self.pending.pop_front();
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::tokenize::kind::TokenKind;
+    use crate::tokenize::position::Position;
+    use crate::tokenize::token::Token;
     use crate::tokenize::tokenizer::Tokenizer;
 
+    fn kinds_and_texts(input: &str) -> Vec<(TokenKind, String)> {
+        return Tokenizer::new(input)
+            .map(|tok| (tok.kind, tok.imm_s))
+            .collect();
+    }
+
     #[test]
     fn tokenize() {
         let input = "<h1>hello, world</h1>";
@@ -326,4 +691,250 @@ mod tests {
         let token = tokenizer.tokenize();
         println!("{:#?}", token)
     }
+
+    #[test]
+    fn iterator_ends_with_eof() {
+        let mut tokenizer = Tokenizer::new("<a>");
+        let kinds: Vec<TokenKind> = tokenizer.by_ref().map(|tok| tok.kind).collect();
+        assert_eq!(kinds, vec![TokenKind::StartTag, TokenKind::Eof]);
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn text_with_symbols_is_one_token() {
+        assert_eq!(
+            kinds_and_texts("<p>a - b, 1.2.3 x/y & z < w = v!</p>"),
+            vec![
+                (TokenKind::StartTag, "p".to_string()),
+                (TokenKind::Text, "a - b, 1.2.3 x/y & z < w = v!".to_string()),
+                (TokenKind::EndTag, "p".to_string()),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_quoted_unquoted_and_empty() {
+        let input = "<INPUT Type=text disabled value='a \"b\"' data-x = \"1\"/>";
+        let tok: Token = Tokenizer::new(input).next().unwrap();
+        assert_eq!(tok.kind, TokenKind::StartTag);
+        // タグ名は書かれたまま
+        assert_eq!(tok.imm_s, "INPUT");
+        assert!(tok.self_closing);
+
+        let attrs: Vec<(&str, Option<&str>)> = tok
+            .attrs
+            .iter()
+            .map(|a| (a.name.as_str(), a.value.as_deref()))
+            .collect();
+        assert_eq!(
+            attrs,
+            vec![
+                ("Type", Some("text")),
+                ("disabled", None),
+                ("value", Some("a \"b\"")),
+                ("data-x", Some("1")),
+            ]
+        );
+
+        let slice = |i: usize| {
+            let span = tok.attrs[i].value_span.as_ref().unwrap();
+            return &input[span.start.at_byte..span.end.at_byte];
+        };
+        assert_eq!(slice(0), "text");
+        assert_eq!(slice(2), "'a \"b\"'");
+        let name = &tok.attrs[3].name_span;
+        assert_eq!(&input[name.start.at_byte..name.end.at_byte], "data-x");
+    }
+
+    #[test]
+    fn char_refs_are_tokens() {
+        let toks: Vec<Token> = Tokenizer::new("a&amp;b &#x3042;&c<title>&lt;</title>").collect();
+        let found: Vec<(TokenKind, &str, &str)> = toks
+            .iter()
+            .map(|t| (t.kind.clone(), t.imm_s.as_str(), t.decoded.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (TokenKind::Text, "a", ""),
+                (TokenKind::CharRef, "&amp;", "&"),
+                (TokenKind::Text, "b ", ""),
+                (TokenKind::CharRef, "&#x3042;", "あ"),
+                (TokenKind::Text, "&c", ""),
+                (TokenKind::StartTag, "title", ""),
+                (TokenKind::CharRef, "&lt;", "<"),
+                (TokenKind::EndTag, "title", ""),
+                (TokenKind::Eof, "", ""),
+            ]
+        );
+        assert_eq!(toks[3].span.start.at_byte, 8);
+        assert_eq!(toks[3].span.end.at_byte, 16);
+    }
+
+    #[test]
+    fn framework_attribute_names() {
+        let input = "<my-comp data-id=1 aria-label=\"x\" xlink:href=\"#a\" @click=\"f()\" \
+                     :value=\"v\" [prop]=\"p\" (event)=\"h($event)\" [(ngModel)]=\"m\" #ref \
+                     *ngIf=\"ok\" v-on:click.prevent=\"g\" v-bind:someProp=\"s\">";
+        let tok: Token = Tokenizer::new(input).next().unwrap();
+        assert_eq!(tok.imm_s, "my-comp");
+
+        let names: Vec<&str> = tok.attrs.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "data-id",
+                "aria-label",
+                "xlink:href",
+                "@click",
+                ":value",
+                "[prop]",
+                "(event)",
+                "[(ngModel)]",
+                "#ref",
+                "*ngIf",
+                "v-on:click.prevent",
+                "v-bind:someProp",
+            ]
+        );
+        for attr in &tok.attrs {
+            let span = &attr.name_span;
+            assert_eq!(&input[span.start.at_byte..span.end.at_byte], attr.name);
+        }
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            kinds_and_texts("<!-- a -- b --><!----><!--><?xml version=\"1.0\"?><!x>"),
+            vec![
+                (TokenKind::Comment, " a -- b ".to_string()),
+                (TokenKind::Comment, "".to_string()),
+                (TokenKind::Comment, "".to_string()),
+                (TokenKind::Comment, "?xml version=\"1.0\"?".to_string()),
+                (TokenKind::Comment, "x".to_string()),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn doctype_keeps_identifiers() {
+        assert_eq!(
+            kinds_and_texts("<!DOCTYPE HTML  PUBLIC \"-//W3C//DTD HTML 4.01//EN\" >"),
+            vec![
+                (
+                    TokenKind::Doctype,
+                    "html PUBLIC \"-//W3C//DTD HTML 4.01//EN\"".to_string()
+                ),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rcdata_with_a_cut_off_or_multibyte_tag() {
+        assert_eq!(
+            kinds_and_texts("<title>a<"),
+            vec![
+                (TokenKind::StartTag, "title".to_string()),
+                (TokenKind::Text, "a<".to_string()),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds_and_texts("<title><あ</title>"),
+            vec![
+                (TokenKind::StartTag, "title".to_string()),
+                (TokenKind::Text, "<あ".to_string()),
+                (TokenKind::EndTag, "title".to_string()),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn start_tag_running_into_the_next_tag() {
+        let input = "<span\n</div><a b<i>";
+        let toks: Vec<Token> = Tokenizer::new(input).collect();
+        let kinds: Vec<(TokenKind, &str, bool)> = toks
+            .iter()
+            .map(|t| (t.kind.clone(), t.imm_s.as_str(), t.unclosed))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenKind::StartTag, "span", true),
+                (TokenKind::EndTag, "div", false),
+                (TokenKind::StartTag, "a", true),
+                (TokenKind::StartTag, "i", false),
+                (TokenKind::Eof, "", false),
+            ]
+        );
+        assert_eq!(toks[0].raw, "<span\n");
+        assert_eq!(toks[2].raw, "<a b");
+    }
+
+    #[test]
+    fn raw_text_runs_to_its_end_tag() {
+        let script = "if (a<b) { x = \"</div>\"; } // </scripts";
+        let input = format!("<script>{}</SCRIPT ><style>p > a {{}}</style>", script);
+        assert_eq!(
+            kinds_and_texts(&input),
+            vec![
+                (TokenKind::StartTag, "script".to_string()),
+                (TokenKind::Text, script.to_string()),
+                (TokenKind::EndTag, "SCRIPT".to_string()),
+                (TokenKind::StartTag, "style".to_string()),
+                (TokenKind::Text, "p > a {}".to_string()),
+                (TokenKind::EndTag, "style".to_string()),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unfinished_tag_is_kept_as_text() {
+        assert_eq!(
+            kinds_and_texts("a</>b<div class=\"x"),
+            vec![
+                (TokenKind::Text, "a</>b<div class=\"x".to_string()),
+                (TokenKind::Eof, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn token_spans_cover_their_lexemes() {
+        let input = "<a href='x'>\n  hi</a><!-- c -->";
+        let mut tokenizer = Tokenizer::new(input);
+        let mut end = 0;
+        let mut lexemes: Vec<&str> = vec![];
+        for tok in tokenizer.tokenize() {
+            // トークンは隙間なく並ぶ
+            assert_eq!(tok.span.start.at_byte, end);
+            end = tok.span.end.at_byte;
+            lexemes.push(&input[tok.span.start.at_byte..tok.span.end.at_byte]);
+        }
+        assert_eq!(end, input.len());
+        assert_eq!(
+            lexemes,
+            vec!["<a href='x'>", "\n  hi", "</a>", "<!-- c -->", ""]
+        );
+    }
+
+    #[test]
+    fn tokenize_multibyte_text() {
+        let input = "<p>こんにちは😀</p>\n<b>";
+        let toks: Vec<Token> = Tokenizer::new(input).collect();
+
+        assert_eq!(toks[1].imm_s, "こんにちは😀");
+        assert_eq!(toks[1].span.start, Position::new(1, 3, 3, 3, 3));
+        // 😀 で char は 1、UTF-16 は 2、バイトは 4 進む
+        assert_eq!(toks[1].span.end, Position::new(1, 9, 10, 9, 22));
+        assert_eq!(toks[4].imm_s, "b");
+        assert_eq!(toks[4].span.start, Position::new(2, 0, 0, 14, 27));
+        assert_eq!(toks[4].name_span().start, Position::new(2, 1, 1, 15, 28));
+    }
 }